@@ -0,0 +1,162 @@
+use errors::*;
+use structs::{Job, JobInstance, JobSettings, Program, ResponseFiles, Search};
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use serde_json;
+
+/// Serializes through `serde_json::Value`, whose maps are sorted, so that specs containing
+/// HashMaps hash the same way every time.
+fn canonical<T: ::serde::Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::to_value(value)?)?)
+}
+
+/// Computes the cache key of a job's expansion: a hash of everything the expansion depends on.
+/// The keys of upstream jobs stand in for their expansions.
+pub fn cache_key(program: &Program,
+                 job: &Job,
+                 settings: &JobSettings,
+                 search: Option<&Search>,
+                 upstream: &[&str])
+                 -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.input_str(env!("CARGO_PKG_VERSION"));
+    hasher.input_str(&canonical(program)?);
+    hasher.input_str(&canonical(job)?);
+    hasher.input_str(&canonical(settings)?);
+    hasher.input_str(&canonical(&search)?);
+    for key in upstream {
+        hasher.input_str(key);
+    }
+    Ok(hasher.result_str())
+}
+
+#[derive(Serialize)]
+struct CacheEntryRef<'a> {
+    base: usize,
+    upstream: &'a [(usize, usize)],
+    instances: &'a [JobInstance],
+    response_files: Vec<&'a ResponseFiles>,
+}
+
+/// A job's stored expansion, along with the ids it was planned with.
+#[derive(Deserialize)]
+pub struct CacheEntry {
+    /// The id of the first instance.
+    base: usize,
+    /// The first id and number of instances of each upstream job.
+    upstream: Vec<(usize, usize)>,
+    instances: Vec<JobInstance>,
+    /// The response files of each instance, which plans leave out.
+    #[serde(default)]
+    response_files: Vec<ResponseFiles>,
+}
+
+impl CacheEntry {
+    /// Renumbers the stored instances to start at `base`, and points their dependencies at the
+    /// upstream instances as they are numbered in the current plan.
+    pub fn rebase(self, base: usize, upstream: &[(usize, usize)]) -> Vec<JobInstance> {
+        let CacheEntry { base: old_base, upstream: old_upstream, instances, response_files } = self;
+        let mut response_files = response_files.into_iter();
+        instances.into_iter()
+            .map(|mut inst| {
+                inst.response_files = response_files.next().unwrap_or_default();
+                inst.id = inst.id.map(|id| id - old_base + base);
+                inst.depends = inst.depends
+                    .iter()
+                    .map(|&dep| {
+                        old_upstream.iter()
+                            .zip(upstream)
+                            .find(|&(&(start, len), _)| dep >= start && dep < start + len)
+                            .map(|(&(start, _), &(new_start, _))| dep - start + new_start)
+                            .unwrap_or(dep)
+                    })
+                    .collect();
+                inst
+            })
+            .collect()
+    }
+}
+
+/// A directory of planned job expansions, keyed by `cache_key`.
+pub struct PlanCache {
+    dir: PathBuf,
+}
+
+impl PlanCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> PlanCache {
+        PlanCache { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Loads a stored expansion. Missing or unreadable entries are treated as a cache miss.
+    pub fn load(&self, key: &str) -> Option<CacheEntry> {
+        File::open(self.path(key))
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+    }
+
+    pub fn store(&self,
+                 key: &str,
+                 base: usize,
+                 upstream: &[(usize, usize)],
+                 instances: &[JobInstance])
+                 -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntryRef {
+            base: base,
+            upstream: upstream,
+            instances: instances,
+            response_files: instances.iter().map(|inst| &inst.response_files).collect(),
+        };
+        serde_json::to_writer(&mut BufWriter::new(File::create(self.path(key))?), &entry)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use structs::*;
+    use serde_yaml;
+    use std::env;
+    use std::fs::{self, File};
+
+    #[test]
+    fn cached_plan_matches() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+        let map = hashmap!{
+            "interdict".to_string() => prog,
+            "interdict-validate".to_string() => validate,
+        };
+
+        let dir = env::temp_dir().join("waluigi-cache-test");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = PlanCache::new(dir.clone());
+
+        let fresh = exp.plan(&map).unwrap();
+        let stored = exp.plan_cached(&map, Some(&cache)).unwrap();
+        let reused = exp.plan_cached(&map, Some(&cache)).unwrap();
+        assert!(fs::read_dir(&dir).unwrap().count() == 2);
+
+        for plan in &[stored, reused] {
+            assert!(plan.len() == fresh.len());
+            for (a, b) in plan.iter().zip(&fresh) {
+                assert!(a.id == b.id && a.hash == b.hash && a.depends == b.depends);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use errors::*;
+
+use regex::Regex;
+
+/// The parts of the CLI that completions are generated for.
+struct Cli {
+    subcommands: Vec<String>,
+    /// Long flags, and whether each takes an argument.
+    flags: Vec<(String, bool)>,
+}
+
+impl Cli {
+    /// Reads subcommands and flags out of the docopt usage string, so completions never drift
+    /// from what the parser accepts.
+    fn from_usage(usage: &str) -> Cli {
+        let subcommand = Regex::new(r"^\s+waluigi ([a-z][a-z-]*)").unwrap();
+        let flag = Regex::new(r"^\s+(?:-\w )?--([a-z][a-z-]*)( <[^>]+>)?").unwrap();
+
+        let mut cli = Cli {
+            subcommands: vec![],
+            flags: vec![],
+        };
+        for line in usage.lines() {
+            if let Some(caps) = subcommand.captures(line) {
+                let name = caps.get(1).unwrap().as_str().to_string();
+                if !cli.subcommands.contains(&name) {
+                    cli.subcommands.push(name);
+                }
+            } else if let Some(caps) = flag.captures(line) {
+                cli.flags.push((caps.get(1).unwrap().as_str().to_string(), caps.get(2).is_some()));
+            }
+        }
+        cli
+    }
+
+    fn long_flags(&self) -> Vec<String> {
+        self.flags.iter().map(|&(ref name, _)| format!("--{}", name)).collect()
+    }
+}
+
+const SHELLS: &'static str = "bash zsh fish";
+const FORMATS: &'static str = "json msgpack";
+
+fn bash(cli: &Cli, programs: &[String]) -> String {
+    let jobs = programs.iter().map(|p| format!("{}=", p)).collect::<Vec<_>>().join(" ");
+    format!(r#"_waluigi() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if [ "$COMP_CWORD" -eq 1 ]; then
+        COMPREPLY=($(compgen -W "{subcommands}" -- "$cur"))
+        return
+    fi
+    case "$prev" in
+        --priority)
+            compopt -o nospace
+            COMPREPLY=($(compgen -W "{jobs}" -- "$cur"))
+            return;;
+        --format)
+            COMPREPLY=($(compgen -W "{formats}" -- "$cur"))
+            return;;
+        completions)
+            COMPREPLY=($(compgen -W "{shells}" -- "$cur"))
+            return;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -F _waluigi waluigi
+"#,
+            subcommands = cli.subcommands.join(" "),
+            jobs = jobs,
+            formats = FORMATS,
+            shells = SHELLS,
+            flags = cli.long_flags().join(" "))
+}
+
+fn zsh(cli: &Cli, programs: &[String]) -> String {
+    format!(r#"#compdef waluigi
+
+_waluigi() {{
+    local -a subcommands flags programs
+    subcommands=({subcommands})
+    flags=({flags})
+    programs=({programs})
+    if (( CURRENT == 2 )); then
+        compadd -a subcommands
+        return
+    fi
+    case $words[CURRENT-1] in
+        --priority) compadd -S '=' -a programs; return;;
+        --format) compadd {formats}; return;;
+        completions) compadd {shells}; return;;
+    esac
+    if [[ $PREFIX == -* ]]; then
+        compadd -a flags
+    else
+        _files
+    fi
+}}
+
+_waluigi "$@"
+"#,
+            subcommands = cli.subcommands.join(" "),
+            flags = cli.long_flags().join(" "),
+            programs = programs.join(" "),
+            formats = FORMATS,
+            shells = SHELLS)
+}
+
+fn fish(cli: &Cli, programs: &[String]) -> String {
+    let mut out = format!("complete -c waluigi -f -n '__fish_use_subcommand' -a '{}'\n",
+                          cli.subcommands.join(" "));
+    out.push_str(&format!("complete -c waluigi -f -n '__fish_seen_subcommand_from completions' \
+                           -a '{}'\n",
+                          SHELLS));
+    for &(ref name, takes_arg) in &cli.flags {
+        let values = match name.as_str() {
+            "priority" => {
+                format!(" -x -a '{}'",
+                        programs.iter().map(|p| format!("{}=", p)).collect::<Vec<_>>().join(" "))
+            }
+            "format" => format!(" -x -a '{}'", FORMATS),
+            _ if takes_arg => " -r".to_string(),
+            _ => "".to_string(),
+        };
+        out.push_str(&format!("complete -c waluigi -l {}{}\n", name, values));
+    }
+    out
+}
+
+/// Renders a completion script for `shell` covering the subcommands and flags of `usage`, and
+/// the names of the given programs.
+pub fn completions(shell: &str, usage: &str, programs: &[String]) -> Result<String> {
+    let cli = Cli::from_usage(usage);
+    match shell {
+        "bash" => Ok(bash(&cli, programs)),
+        "zsh" => Ok(zsh(&cli, programs)),
+        "fish" => Ok(fish(&cli, programs)),
+        _ => Err(ErrorKind::UnknownShell(shell.to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const USAGE: &'static str = "
+Usage:
+  waluigi debug <experiment> [options]
+  waluigi debug <experiment> --extra
+  waluigi completions <shell>
+
+Options:
+  -h --help             Show this screen.
+  --priority <job=n>    Override the priority.
+";
+
+    #[test]
+    fn parse_usage() {
+        let cli = Cli::from_usage(USAGE);
+        assert!(cli.subcommands == vec!["debug".to_string(), "completions".to_string()]);
+        assert!(cli.flags == vec![("help".to_string(), false), ("priority".to_string(), true)]);
+    }
+
+    #[test]
+    fn unknown_shell() {
+        assert!(completions("tcsh", USAGE, &[]).is_err());
+        assert!(completions("fish", USAGE, &["curv".to_string()])
+            .unwrap()
+            .contains("-l priority -x -a 'curv='"));
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 use structs::*;
+use std::collections::HashMap;
 
 error_chain! {
     types {
@@ -13,6 +14,9 @@ error_chain! {
     foreign_links {
         IO(::std::io::Error);
         Yaml(::serde_yaml::Error);
+        Json(::serde_json::Error);
+        MsgPackEncode(::rmp::encode::ValueWriteError);
+        MsgPackDecode(::rmp::decode::ValueReadError);
     }
 
     errors {
@@ -45,5 +49,445 @@ error_chain! {
             description("job has unknown dependency")
             display("job {} has {} listed as a dependency, but no previous job provides {}", job, dependency, dependency)
         }
+
+        CommandTooLong(program: String, params: HashMap<String, FieldData>, length: usize) {
+            description("rendered command is too long")
+            display("command for {} with parameters {:?} is {} bytes long, which exceeds the limit of {} (consider a response_file for long fields)", program, params, length, ARG_MAX)
+        }
+
+        ArgumentTooLong(start: String, length: usize) {
+            description("argument is too long")
+            display("argument {}... is {} bytes long, which exceeds the limit of {} for one argument (consider a response_file for long fields)", start, length, MAX_ARG_STRLEN)
+        }
+
+        UnknownJob(name: String) {
+            description("no job runs the given program")
+            display("no job in the experiment runs {}", name)
+        }
+
+        InvalidExpression(expr: String, reason: String) {
+            description("invalid expression")
+            display("invalid expression {}: {}", expr, reason)
+        }
+
+        UnknownVariable(name: String) {
+            description("expression refers to an unknown parameter")
+            display("expression refers to unknown parameter {}", name)
+        }
+
+        InvalidEstimate(program: String, params: HashMap<String, FieldData>, estimate: f64) {
+            description("runtime estimate is not a finite number")
+            display("runtime estimate of {} with parameters {:?} is {}, not a finite number", program, params, estimate)
+        }
+
+        UnknownPlanFormat(name: String) {
+            description("unknown plan format")
+            display("unknown plan format {}. available: json, msgpack", name)
+        }
+
+        ShardsWithoutOutput {
+            description("sharding requires an output path")
+            display("--shards and --shard-size require --output to name the shard files")
+        }
+
+        UnknownShell(name: String) {
+            description("unknown shell for completions")
+            display("cannot generate completions for {}. available: bash, zsh, fish", name)
+        }
+
+        FileExists(path: String) {
+            description("refusing to overwrite an existing file")
+            display("refusing to overwrite existing file {}", path)
+        }
+
+        DeniedWarnings(count: usize) {
+            description("warnings denied by --deny-warnings")
+            display("{} warning(s) found and --deny-warnings is set", count)
+        }
+
+        DuplicateProgram(name: String, first: String, second: String) {
+            description("program defined in more than one file")
+            display("program {} is defined in both {} and {}. use --prefer to pick one", name, first, second)
+        }
+
+        UnwiredField(job: String, field: String) {
+            description("required field of a dependent job is never filled")
+            display("field {} of job {} is required, but neither the job nor the jobs it depends on provide it", field, job)
+        }
+
+        MiswiredField(job: String, field: String, upstream: String, dtype: FieldType) {
+            description("dependent job field filled by an output of the wrong type")
+            display("field {} of job {} has type {:?}, but is filled by an output of {}, which can only fill str fields", field, job, dtype, upstream)
+        }
+
+        UnsupportedSpecVersion(path: String, version: u32) {
+            description("spec is newer than this version of waluigi")
+            display("{} has spec_version {}, but this waluigi only understands versions up to {}. upgrade waluigi to use it", path, version, SPEC_VERSION)
+        }
+
+        InvalidSpecVersion(path: String, version: u32) {
+            description("spec version below 1")
+            display("{} has spec_version {}, but versions start at 1", path, version)
+        }
+
+        UnknownColumn(path: String, column: String) {
+            description("values file has no such column")
+            display("{} has no column named {}", path, column)
+        }
+
+        InvalidRange(name: String, setting: FieldSetting) {
+            description("range with an endpoint or step that is not a number")
+            display("range {:?} for {} has an endpoint or step that is not a number", setting, name)
+        }
+
+        InvalidStep(name: String, setting: FieldSetting) {
+            description("range with a step that is not positive")
+            display("range {:?} for {} has a step that is not positive, so it never reaches its end", setting, name)
+        }
+
+        UnknownParameter(name: String) {
+            description("no job sets the given parameter")
+            display("no job in the experiment sets {}. use <job>.{} to add it to a job", name, name)
+        }
+
+        InvalidOverride(spec: String) {
+            description("invalid override")
+            display("invalid override {}, expected [<job>.]<name>=<value>", spec)
+        }
+
+        UnknownVar(name: String) {
+            description("experiment declares no such variable")
+            display("the experiment declares no variable {}", name)
+        }
+
+        InvalidPattern(program: String, pattern: String, reason: String) {
+            description("invalid pattern in program spec")
+            display("invalid pattern {} in {}: {}", pattern, program, reason)
+        }
+
+        ChecksumMismatch(path: String, expected: String, actual: String) {
+            description("file does not have the expected checksum")
+            display("{} has SHA-256 {}, but {} was expected. rebuild it or update the checksum", path, actual, expected)
+        }
+
+        BinaryNotFound(program: String, bin: String, searched: Vec<String>) {
+            description("program binary is missing or not executable")
+            display("binary {} of {} is missing or not executable. searched: {}", bin, program, searched.join(", "))
+        }
+
+        InvalidRender(program: String, field: String, render: String, dtype: FieldType) {
+            description("field has a render format its type does not support")
+            display("field {} of {} has render {}, which fields of type {:?} do not support", field, program, render, dtype)
+        }
+
+        PatternMismatch(field: String, value: String, pattern: String) {
+            description("parameter value does not match the pattern of its field")
+            display("value {} of field {} does not match its pattern {}", value, field, pattern)
+        }
+
+        OutOfRange(field: String, value: f64, bound: String) {
+            description("parameter value is outside the bounds of its field")
+            display("value {} of field {} is out of range: it must be {}", value, field, bound)
+        }
+
+        ExtraParameter(job: String, parameter: String, suggestions: Vec<String>) {
+            description("job sets a parameter its program has no field for")
+            display("job {} sets parameter {}, but the program has no such field{}. pass --allow-extra-params to ignore it", job, parameter, if suggestions.is_empty() { String::new() } else { format!(" (did you mean {}?)", suggestions.join(" or ")) })
+        }
+
+        UnknownSharedParameters(job: String, name: String) {
+            description("job uses an unknown block of shared parameters")
+            display("job {} uses {}, but the experiment has no such block in shared_parameters", job, name)
+        }
+
+        InactiveField(field: String, program: String, condition: String) {
+            description("parameter given for a field that its conditions leave unused")
+            display("field {} of {} is only used when {}, but is given a value where it is not", field, program, condition)
+        }
+
+        MissingOutputPath(program: String, output: String) {
+            description("file output has no path")
+            display("output {} of {} is a file, but has no path", output, program)
+        }
+
+        OutputCollision(path: String, first: String, second: String) {
+            description("two instances write the same output file")
+            display("{} and {} both write {}, so one would overwrite the other (does the path leave out a parameter that tells them apart?)", first, second, path)
+        }
+
+        InvalidPriority(spec: String) {
+            description("invalid priority override")
+            display("invalid priority override {}, expected <job>=<priority>", spec)
+        }
+    }
+}
+
+/// Broad kinds of failure, each exiting with its own status so that scripts can branch on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// A bug, or a failure of the environment such as an unwritable file.
+    Internal,
+    /// A spec or plan file could not be parsed.
+    Parse,
+    /// The specs parsed, but are inconsistent or were used incorrectly.
+    Validation,
+    /// The specs are valid, but the experiment could not be planned.
+    Planning,
+    /// Some jobs failed. Reserved for the tools that run plans.
+    Execution,
+}
+
+impl ErrorClass {
+    /// The documented exit status for the class. 2 is left to command line errors.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorClass::Internal => 1,
+            ErrorClass::Parse => 3,
+            ErrorClass::Validation => 4,
+            ErrorClass::Planning => 5,
+            ErrorClass::Execution => 6,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ErrorClass::Internal => "internal",
+            ErrorClass::Parse => "parse",
+            ErrorClass::Validation => "validation",
+            ErrorClass::Planning => "planning",
+            ErrorClass::Execution => "execution",
+        }
+    }
+}
+
+impl ErrorKind {
+    /// A stable, machine-readable name for the error. Codes are never renamed or reused, so
+    /// wrapper scripts can match on them.
+    pub fn code(&self) -> &'static str {
+        match self {
+            &ErrorKind::IO(_) => "io",
+            &ErrorKind::Yaml(_) => "yaml",
+            &ErrorKind::Json(_) => "json",
+            &ErrorKind::MsgPackEncode(_) => "msgpack_encode",
+            &ErrorKind::MsgPackDecode(_) => "msgpack_decode",
+            &ErrorKind::FieldMismatch(..) => "field_mismatch",
+            &ErrorKind::InvalidProgram(..) => "invalid_program",
+            &ErrorKind::MissingParameter(..) => "missing_parameter",
+            &ErrorKind::InvalidParameterSetting(..) => "invalid_parameter_setting",
+            &ErrorKind::InvalidParameterData(..) => "invalid_parameter_data",
+            &ErrorKind::UnknownDependency(..) => "unknown_dependency",
+            &ErrorKind::CommandTooLong(..) => "command_too_long",
+            &ErrorKind::ArgumentTooLong(..) => "argument_too_long",
+            &ErrorKind::UnknownJob(..) => "unknown_job",
+            &ErrorKind::InvalidExpression(..) => "invalid_expression",
+            &ErrorKind::UnknownVariable(..) => "unknown_variable",
+            &ErrorKind::InvalidEstimate(..) => "invalid_estimate",
+            &ErrorKind::UnknownPlanFormat(..) => "unknown_plan_format",
+            &ErrorKind::ShardsWithoutOutput => "shards_without_output",
+            &ErrorKind::UnknownShell(..) => "unknown_shell",
+            &ErrorKind::FileExists(..) => "file_exists",
+            &ErrorKind::DeniedWarnings(..) => "denied_warnings",
+            &ErrorKind::DuplicateProgram(..) => "duplicate_program",
+            &ErrorKind::UnwiredField(..) => "unwired_field",
+            &ErrorKind::MiswiredField(..) => "miswired_field",
+            &ErrorKind::UnsupportedSpecVersion(..) => "unsupported_spec_version",
+            &ErrorKind::InvalidSpecVersion(..) => "invalid_spec_version",
+            &ErrorKind::UnknownColumn(..) => "unknown_column",
+            &ErrorKind::InvalidRange(..) => "invalid_range",
+            &ErrorKind::InvalidStep(..) => "invalid_step",
+            &ErrorKind::UnknownParameter(..) => "unknown_parameter",
+            &ErrorKind::InvalidOverride(..) => "invalid_override",
+            &ErrorKind::UnknownVar(..) => "unknown_var",
+            &ErrorKind::InvalidPattern(..) => "invalid_pattern",
+            &ErrorKind::ChecksumMismatch(..) => "checksum_mismatch",
+            &ErrorKind::BinaryNotFound(..) => "binary_not_found",
+            &ErrorKind::InvalidRender(..) => "invalid_render",
+            &ErrorKind::PatternMismatch(..) => "pattern_mismatch",
+            &ErrorKind::OutOfRange(..) => "out_of_range",
+            &ErrorKind::ExtraParameter(..) => "extra_parameter",
+            &ErrorKind::UnknownSharedParameters(..) => "unknown_shared_parameters",
+            &ErrorKind::InactiveField(..) => "inactive_field",
+            &ErrorKind::MissingOutputPath(..) => "missing_output_path",
+            &ErrorKind::OutputCollision(..) => "output_collision",
+            &ErrorKind::InvalidPriority(..) => "invalid_priority",
+            &ErrorKind::Msg(_) => "internal",
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            &ErrorKind::Msg(_) |
+            &ErrorKind::IO(_) |
+            &ErrorKind::MsgPackEncode(_) |
+            &ErrorKind::FileExists(..) => ErrorClass::Internal,
+            &ErrorKind::Yaml(_) |
+            &ErrorKind::Json(_) |
+            &ErrorKind::MsgPackDecode(_) |
+            &ErrorKind::InvalidExpression(..) |
+            &ErrorKind::UnsupportedSpecVersion(..) |
+            &ErrorKind::InvalidSpecVersion(..) => ErrorClass::Parse,
+            &ErrorKind::FieldMismatch(..) |
+            &ErrorKind::InvalidProgram(..) |
+            &ErrorKind::MissingParameter(..) |
+            &ErrorKind::InvalidParameterSetting(..) |
+            &ErrorKind::InvalidParameterData(..) |
+            &ErrorKind::UnknownDependency(..) |
+            &ErrorKind::UnknownJob(..) |
+            &ErrorKind::UnknownVariable(..) |
+            &ErrorKind::UnknownPlanFormat(..) |
+            &ErrorKind::ShardsWithoutOutput |
+            &ErrorKind::UnknownShell(..) |
+            &ErrorKind::DeniedWarnings(..) |
+            &ErrorKind::DuplicateProgram(..) |
+            &ErrorKind::UnwiredField(..) |
+            &ErrorKind::MiswiredField(..) |
+            &ErrorKind::UnknownColumn(..) |
+            &ErrorKind::InvalidRange(..) |
+            &ErrorKind::InvalidStep(..) |
+            &ErrorKind::UnknownParameter(..) |
+            &ErrorKind::InvalidOverride(..) |
+            &ErrorKind::UnknownVar(..) |
+            &ErrorKind::InvalidPattern(..) |
+            &ErrorKind::ChecksumMismatch(..) |
+            &ErrorKind::BinaryNotFound(..) |
+            &ErrorKind::InvalidRender(..) |
+            &ErrorKind::PatternMismatch(..) |
+            &ErrorKind::OutOfRange(..) |
+            &ErrorKind::ExtraParameter(..) |
+            &ErrorKind::UnknownSharedParameters(..) |
+            &ErrorKind::InactiveField(..) |
+            &ErrorKind::MissingOutputPath(..) |
+            &ErrorKind::InvalidPriority(..) => ErrorClass::Validation,
+            &ErrorKind::CommandTooLong(..) => ErrorClass::Planning,
+            &ErrorKind::ArgumentTooLong(..) => ErrorClass::Planning,
+            &ErrorKind::InvalidEstimate(..) => ErrorClass::Planning,
+            &ErrorKind::OutputCollision(..) => ErrorClass::Planning,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rmp::Marker;
+    use rmp::decode::ValueReadError;
+    use rmp::encode::ValueWriteError;
+    use serde_json;
+    use serde_yaml;
+    use std::io;
+
+    #[test]
+    fn class_exit_codes() {
+        // 0 is success and 2 is a command line error
+        let classes = [(ErrorClass::Internal, 1),
+                       (ErrorClass::Parse, 3),
+                       (ErrorClass::Validation, 4),
+                       (ErrorClass::Planning, 5),
+                       (ErrorClass::Execution, 6)];
+        for &(class, code) in &classes {
+            assert!(class.exit_code() == code, "{:?} exits with {}", class, class.exit_code());
+        }
+    }
+
+    #[test]
+    fn codes_are_pinned() {
+        let s = || "x".to_string();
+        let setting = || FieldSetting::Value(FieldData::UInt(0));
+        let io_error = || io::Error::new(io::ErrorKind::Other, "x");
+        let kinds = vec![
+            (ErrorKind::Msg(s()), "internal", ErrorClass::Internal),
+            (ErrorKind::IO(io_error()), "io", ErrorClass::Internal),
+            (ErrorKind::MsgPackEncode(ValueWriteError::InvalidMarkerWrite(io_error())),
+             "msgpack_encode",
+             ErrorClass::Internal),
+            (ErrorKind::FileExists(s()), "file_exists", ErrorClass::Internal),
+            (ErrorKind::Yaml(serde_yaml::from_str::<u32>("x").unwrap_err()),
+             "yaml",
+             ErrorClass::Parse),
+            (ErrorKind::Json(serde_json::from_str::<u32>("x").unwrap_err()),
+             "json",
+             ErrorClass::Parse),
+            (ErrorKind::MsgPackDecode(ValueReadError::TypeMismatch(Marker::Null)),
+             "msgpack_decode",
+             ErrorClass::Parse),
+            (ErrorKind::InvalidExpression(s(), s()), "invalid_expression", ErrorClass::Parse),
+            (ErrorKind::UnsupportedSpecVersion(s(), 9),
+             "unsupported_spec_version",
+             ErrorClass::Parse),
+            (ErrorKind::InvalidSpecVersion(s(), 0), "invalid_spec_version", ErrorClass::Parse),
+            (ErrorKind::FieldMismatch(FieldType::UInt, FieldData::Bool(true)),
+             "field_mismatch",
+             ErrorClass::Validation),
+            (ErrorKind::InvalidProgram(s(), vec![]), "invalid_program", ErrorClass::Validation),
+            (ErrorKind::MissingParameter(s(), s()), "missing_parameter", ErrorClass::Validation),
+            (ErrorKind::InvalidParameterSetting(s(), setting(), FieldType::Bool),
+             "invalid_parameter_setting",
+             ErrorClass::Validation),
+            (ErrorKind::InvalidParameterData(s(), FieldData::UInt(0), FieldType::Bool),
+             "invalid_parameter_data",
+             ErrorClass::Validation),
+            (ErrorKind::UnknownDependency(s(), s()),
+             "unknown_dependency",
+             ErrorClass::Validation),
+            (ErrorKind::UnknownJob(s()), "unknown_job", ErrorClass::Validation),
+            (ErrorKind::UnknownVariable(s()), "unknown_variable", ErrorClass::Validation),
+            (ErrorKind::UnknownPlanFormat(s()), "unknown_plan_format", ErrorClass::Validation),
+            (ErrorKind::ShardsWithoutOutput, "shards_without_output", ErrorClass::Validation),
+            (ErrorKind::UnknownShell(s()), "unknown_shell", ErrorClass::Validation),
+            (ErrorKind::DeniedWarnings(1), "denied_warnings", ErrorClass::Validation),
+            (ErrorKind::DuplicateProgram(s(), s(), s()),
+             "duplicate_program",
+             ErrorClass::Validation),
+            (ErrorKind::UnwiredField(s(), s()), "unwired_field", ErrorClass::Validation),
+            (ErrorKind::MiswiredField(s(), s(), s(), FieldType::Str),
+             "miswired_field",
+             ErrorClass::Validation),
+            (ErrorKind::UnknownColumn(s(), s()), "unknown_column", ErrorClass::Validation),
+            (ErrorKind::InvalidRange(s(), setting()), "invalid_range", ErrorClass::Validation),
+            (ErrorKind::InvalidStep(s(), setting()), "invalid_step", ErrorClass::Validation),
+            (ErrorKind::UnknownParameter(s()), "unknown_parameter", ErrorClass::Validation),
+            (ErrorKind::InvalidOverride(s()), "invalid_override", ErrorClass::Validation),
+            (ErrorKind::UnknownVar(s()), "unknown_var", ErrorClass::Validation),
+            (ErrorKind::InvalidPattern(s(), s(), s()), "invalid_pattern", ErrorClass::Validation),
+            (ErrorKind::ChecksumMismatch(s(), s(), s()),
+             "checksum_mismatch",
+             ErrorClass::Validation),
+            (ErrorKind::BinaryNotFound(s(), s(), vec![]),
+             "binary_not_found",
+             ErrorClass::Validation),
+            (ErrorKind::InvalidRender(s(), s(), s(), FieldType::Path),
+             "invalid_render",
+             ErrorClass::Validation),
+            (ErrorKind::PatternMismatch(s(), s(), s()),
+             "pattern_mismatch",
+             ErrorClass::Validation),
+            (ErrorKind::OutOfRange(s(), 1.0, s()), "out_of_range", ErrorClass::Validation),
+            (ErrorKind::ExtraParameter(s(), s(), vec![]),
+             "extra_parameter",
+             ErrorClass::Validation),
+            (ErrorKind::UnknownSharedParameters(s(), s()),
+             "unknown_shared_parameters",
+             ErrorClass::Validation),
+            (ErrorKind::InactiveField(s(), s(), s()), "inactive_field", ErrorClass::Validation),
+            (ErrorKind::MissingOutputPath(s(), s()),
+             "missing_output_path",
+             ErrorClass::Validation),
+            (ErrorKind::InvalidPriority(s()), "invalid_priority", ErrorClass::Validation),
+            (ErrorKind::CommandTooLong(s(), HashMap::new(), ARG_MAX + 1),
+             "command_too_long",
+             ErrorClass::Planning),
+            (ErrorKind::ArgumentTooLong(s(), MAX_ARG_STRLEN + 1),
+             "argument_too_long",
+             ErrorClass::Planning),
+            (ErrorKind::InvalidEstimate(s(), HashMap::new(), -1.0),
+             "invalid_estimate",
+             ErrorClass::Planning),
+            (ErrorKind::OutputCollision(s(), s(), s()), "output_collision", ErrorClass::Planning),
+        ];
+
+        for (kind, code, class) in kinds {
+            assert!(kind.code() == code, "{:?} has code {}", kind, kind.code());
+            assert!(kind.class() == class, "{:?} has class {:?}", kind, kind.class());
+        }
     }
 }
@@ -0,0 +1,242 @@
+use errors::*;
+
+/// A small arithmetic expression over job parameters, e.g. `n * k * 1e-6`. Parameters may also
+/// be written as template placeholders, e.g. `<k> / 2`, which allows names with dashes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, reason: &str) -> Result<T> {
+        Err(ErrorKind::InvalidExpression(self.src.to_string(), reason.to_string()).into())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Expr::Bin(Op::Pow, Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if !self.eat(')') {
+                    return self.error("unbalanced parentheses");
+                }
+                Ok(inner)
+            }
+            Some(c) if c.is_digit(10) || c == '.' => {
+                let start = self.pos;
+                while self.pos < self.chars.len() {
+                    let c = self.chars[self.pos];
+                    let exponent_sign = (c == '-' || c == '+') && self.pos > start &&
+                                        (self.chars[self.pos - 1] == 'e' ||
+                                         self.chars[self.pos - 1] == 'E');
+                    if c.is_digit(10) || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                let text = self.chars[start..self.pos].iter().cloned().collect::<String>();
+                match text.parse::<f64>() {
+                    Ok(v) => Ok(Expr::Num(v)),
+                    Err(_) => self.error(&format!("invalid number {}", text)),
+                }
+            }
+            Some('<') => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.pos < self.chars.len() && self.chars[self.pos] != '>' {
+                    self.pos += 1;
+                }
+                if self.pos == self.chars.len() || self.pos == start {
+                    return self.error("unterminated placeholder");
+                }
+                let name = self.chars[start..self.pos].iter().cloned().collect();
+                self.pos += 1;
+                Ok(Expr::Var(name))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self.pos < self.chars.len() &&
+                      (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_') {
+                    self.pos += 1;
+                }
+                Ok(Expr::Var(self.chars[start..self.pos].iter().cloned().collect()))
+            }
+            Some(c) => self.error(&format!("unexpected {}", c)),
+            None => self.error("unexpected end of expression"),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Expr> {
+        let mut parser = Parser {
+            src: src,
+            chars: src.chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.expr()?;
+        if parser.peek().is_some() {
+            return parser.error("trailing input");
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression, resolving variables through `lookup`.
+    pub fn eval<F>(&self, lookup: &F) -> Result<f64>
+        where F: Fn(&str) -> Option<f64>
+    {
+        match self {
+            &Expr::Num(v) => Ok(v),
+            &Expr::Var(ref name) => {
+                lookup(name).ok_or_else(|| ErrorKind::UnknownVariable(name.clone()).into())
+            }
+            &Expr::Neg(ref e) => Ok(-e.eval(lookup)?),
+            &Expr::Bin(op, ref lhs, ref rhs) => {
+                let (l, r) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                Ok(match op {
+                    Op::Add => l + r,
+                    Op::Sub => l - r,
+                    Op::Mul => l * r,
+                    Op::Div => l / r,
+                    Op::Pow => l.powf(r),
+                })
+            }
+        }
+    }
+
+    /// Names of every variable referenced by the expression.
+    #[cfg(test)]
+    pub fn variables(&self) -> Vec<String> {
+        match self {
+            &Expr::Num(_) => vec![],
+            &Expr::Var(ref name) => vec![name.clone()],
+            &Expr::Neg(ref e) => e.variables(),
+            &Expr::Bin(_, ref lhs, ref rhs) => {
+                let mut vars = lhs.variables();
+                vars.extend(rhs.variables());
+                vars
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_precedence() {
+        let expr = Expr::parse("1 + 2 * 3 ^ 2").unwrap();
+        assert!(expr.eval(&|_| None).unwrap() == 19.0);
+        assert!(Expr::parse("-(1 + 2) * 2").unwrap().eval(&|_| None).unwrap() == -6.0);
+    }
+
+    #[test]
+    fn eval_variables() {
+        let expr = Expr::parse("n*k*1e-6").unwrap();
+        let value = expr.eval(&|name| match name {
+                "n" => Some(1000.0),
+                "k" => Some(100.0),
+                _ => None,
+            })
+            .unwrap();
+        assert!((value - 0.1).abs() < 1e-12);
+        assert!(expr.eval(&|_| None).is_err());
+        assert!(expr.variables() == vec!["n".to_string(), "k".to_string()]);
+        assert!(Expr::parse("<random-pct> * 2").unwrap().variables() ==
+                vec!["random-pct".to_string()]);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1 + 2").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("<k").is_err());
+        assert!(Expr::parse("<>").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use errors::*;
+use structs::SPEC_VERSION;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn program_spec(name: &str, bin: &str) -> String {
+    format!(r#"---
+spec_version: {version}
+# Program specification for {name}.
+name: {name}
+# path to the binary, relative to the directory waluigi is run from
+bin: {bin}
+# the command line. each <field> placeholder is filled with that parameter. optioned fields are
+# inserted at the <options> marker, or appended if there is none. <threads> is filled with the
+# thread count of the job.
+format: '<options> <graph> <k> --threads <threads>'
+# values the program reports on stdout
+outputs:
+  ratio:
+    msg: "approximation ratio"
+    aka: ["ratio"]
+fields:
+  graph:
+    type: path
+  k:
+    type: uint
+  epsilon:
+    type: float
+    aka: ["ε"]
+    # a field with an option may be left out of an experiment. when given, it is rendered
+    # through its option template.
+    option: '--epsilon <epsilon>'
+  verbose:
+    type: bool
+    # bools only render their option when true
+    option: '--verbose'
+# estimated runtime of one job in seconds: a constant, or a formula over numeric parameters
+# runtime: 'k * 0.5 / threads'
+"#,
+            version = SPEC_VERSION,
+            name = name,
+            bin = bin)
+}
+
+fn experiment_spec(name: &str) -> String {
+    format!(r#"---
+spec_version: {version}
+# settings for every job, unless the job sets its own
+defaults:
+  threads: 1
+jobs:
+  - run: {name}
+    parameters:
+      # a list: every combination of parameter values becomes one job
+      graph: ["data/a.bin", "data/b.bin"]
+      # an inclusive range
+      k: {{from: 10, to: 30, step: 10}}
+      epsilon: [0.1, 0.05]
+      # a single value
+      verbose: false
+    # run every combination this many times
+    repetitions: 3
+  # a job that runs once for each instance of the jobs it depends on, receiving their
+  # parameters and outputs:
+  # - run: {name}-validate
+  #   on_each: [{name}]
+  #   parameters:
+  #     epsilon: 0.01
+"#,
+            version = SPEC_VERSION,
+            name = name)
+}
+
+/// Writes `contents` to a new file, refusing to replace an existing one.
+fn create(path: &Path, contents: &str) -> Result<()> {
+    if path.exists() {
+        return Err(ErrorKind::FileExists(path.to_string_lossy().into_owned()).into());
+    }
+    OpenOptions::new().write(true).create_new(true).open(path)?.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Writes skeleton program and experiment specs for a new program into `dir`, returning their
+/// paths.
+pub fn init(name: &str, bin: Option<&str>, dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    let bin = bin.map(|b| b.to_string()).unwrap_or_else(|| format!("target/release/{}", name));
+    let program = dir.join(format!("{}.yaml", name));
+    let experiment = dir.join(format!("exp-{}.yaml", name));
+
+    fs::create_dir_all(dir)?;
+    create(&program, &program_spec(name, &bin))?;
+    create(&experiment, &experiment_spec(name))?;
+    Ok((program, experiment))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use structs::*;
+    use serde_yaml;
+
+    #[test]
+    fn skeleton_plans() {
+        let prog: Program = serde_yaml::from_str(&program_spec("foo", "bin/foo")).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&experiment_spec("foo")).unwrap();
+
+        let plan = exp.plan(&hashmap!{ "foo".to_string() => prog }).unwrap();
+        assert!(plan.len() == 36);
+    }
+}
@@ -0,0 +1,113 @@
+use errors::*;
+use structs::SPEC_VERSION;
+
+use regex::Regex;
+use serde_yaml::{self, Value};
+
+/// Steps that rewrite a spec from one layout to the next: `MIGRATIONS[i]` upgrades version
+/// `i + 1` to `i + 2`. Append a step here whenever `SPEC_VERSION` is bumped.
+const MIGRATIONS: &'static [fn(&mut Value) -> Result<()>] = &[];
+
+/// Just enough of a spec to read its version, whatever its layout.
+#[derive(Deserialize)]
+struct Versioned {
+    spec_version: Option<u32>,
+}
+
+/// Fails if the spec at `path` is of a newer version than this build understands, or of a
+/// version that never existed.
+pub fn check_version(path: &str, version: Option<u32>) -> Result<()> {
+    match version {
+        Some(0) => Err(ErrorKind::InvalidSpecVersion(path.to_string(), 0).into()),
+        Some(v) if v > SPEC_VERSION => {
+            Err(ErrorKind::UnsupportedSpecVersion(path.to_string(), v).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the `spec_version` of the spec in `source`, whatever its layout.
+pub fn version(source: &str) -> Result<Option<u32>> {
+    Ok(serde_yaml::from_str::<Versioned>(source)?.spec_version)
+}
+
+/// Fails if the spec in `source` is of a newer version than this build understands. Checked
+/// before the spec itself is parsed, so that newer layouts get this error instead of a parse
+/// error.
+pub fn check_source(path: &str, source: &str) -> Result<()> {
+    check_version(path, version(source)?)
+}
+
+/// Sets the top-level `spec_version` of a spec to the current version, keeping its formatting
+/// and comments.
+fn stamp(source: &str) -> String {
+    let existing = Regex::new(r"(?m)^spec_version:.*$").unwrap();
+    let line = format!("spec_version: {}", SPEC_VERSION);
+    if existing.is_match(source) {
+        return existing.replace(source, line.as_str()).into_owned();
+    }
+
+    // after the document marker, if there is one
+    let start = Regex::new(r"(?m)^---[ \t]*\n").unwrap();
+    match start.find(source) {
+        Some(m) => format!("{}{}\n{}", &source[..m.end()], line, &source[m.end()..]),
+        None => format!("{}\n{}", line, source),
+    }
+}
+
+/// Rewrites a program or experiment spec to the current layout. Returns `None` if it is already
+/// current.
+///
+/// Specs that only need their version stamped keep their formatting; specs whose layout changes
+/// are re-serialized, which drops their comments.
+pub fn migrate(path: &str, source: &str) -> Result<Option<String>> {
+    let version = version(source)?;
+    check_version(path, version)?;
+    let from = version.unwrap_or(1);
+    if version == Some(SPEC_VERSION) {
+        return Ok(None);
+    }
+
+    let source = if from < SPEC_VERSION {
+        let mut value: Value = serde_yaml::from_str(source)?;
+        for step in &MIGRATIONS[(from - 1) as usize..] {
+            step(&mut value)?;
+        }
+        serde_yaml::to_string(&value)?
+    } else {
+        source.to_string()
+    };
+    Ok(Some(stamp(&source)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use structs::*;
+    use serde_yaml;
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn migrate_unversioned() {
+        let mut source = String::new();
+        File::open("programs/curv.yaml").unwrap().read_to_string(&mut source).unwrap();
+
+        let migrated = migrate("curv.yaml", &source).unwrap().unwrap();
+        let prog: Program = serde_yaml::from_str(&migrated).unwrap();
+        assert!(prog.spec_version == Some(SPEC_VERSION));
+        assert!(migrate("curv.yaml", &migrated).unwrap().is_none());
+    }
+
+    #[test]
+    fn reject_newer() {
+        let source = format!("spec_version: {}\njobs: []\n", SPEC_VERSION + 1);
+        assert!(migrate("exp.yaml", &source).is_err());
+    }
+
+    #[test]
+    fn reject_zero() {
+        let err = migrate("exp.yaml", "spec_version: 0\njobs: []\n").unwrap_err();
+        assert!(err.kind().code() == "invalid_spec_version");
+    }
+}
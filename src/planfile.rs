@@ -0,0 +1,563 @@
+use errors::*;
+use structs::{JobInstance, ProgramHeader};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use serde_json::{self, Map, Value};
+use rmp;
+use rmp::Marker;
+use flate2;
+use zstd;
+
+/// On-disk encodings of a plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanFormat {
+    /// One JSON object per line.
+    Json,
+    /// One MessagePack value per job, back to back.
+    MsgPack,
+}
+
+impl PlanFormat {
+    /// Guesses the format of a plan file from its extension, ignoring any compression extension,
+    /// and defaulting to JSON.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> PlanFormat {
+        let path = path.as_ref();
+        let path = if Compression::from_path(path) == Compression::None {
+            path
+        } else {
+            path.file_stem().map(Path::new).unwrap_or(path)
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("msgpack") | Some("mpk") => PlanFormat::MsgPack,
+            _ => PlanFormat::Json,
+        }
+    }
+}
+
+impl FromStr for PlanFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<PlanFormat> {
+        match s {
+            "json" => Ok(PlanFormat::Json),
+            "msgpack" => Ok(PlanFormat::MsgPack),
+            _ => Err(ErrorKind::UnknownPlanFormat(s.to_string()).into()),
+        }
+    }
+}
+
+/// Compression applied to a plan file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Picks the compression of a plan file from its extension (`.gz` or `.zst`).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Opens a plan file for reading, transparently decompressing it.
+pub fn open_plan<P: AsRef<Path>>(path: P) -> Result<PlanReader<Box<Read>>> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let reader: Box<Read> = match Compression::from_path(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::GzDecoder::new(file)?),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+    };
+    Ok(PlanReader::new(reader, PlanFormat::from_path(path)))
+}
+
+/// Destination of a plan: stdout or a file, compressed according to its extension.
+pub enum Sink {
+    Stdout(io::Stdout),
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<BufWriter<File>>),
+}
+
+impl Sink {
+    pub fn create(path: Option<&str>) -> Result<Sink> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(Sink::Stdout(io::stdout())),
+        };
+
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_path(path) {
+            Compression::None => Sink::Plain(file),
+            Compression::Gzip => {
+                Sink::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::Default))
+            }
+            Compression::Zstd => Sink::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Flushes the sink and writes any trailer the compression format needs.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Sink::Stdout(mut out) => out.flush()?,
+            Sink::Plain(mut file) => file.flush()?,
+            Sink::Gzip(enc) => enc.finish()?.flush()?,
+            Sink::Zstd(enc) => enc.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            &mut Sink::Stdout(ref mut out) => out.write(buf),
+            &mut Sink::Plain(ref mut file) => file.write(buf),
+            &mut Sink::Gzip(ref mut enc) => enc.write(buf),
+            &mut Sink::Zstd(ref mut enc) => enc.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            &mut Sink::Stdout(ref mut out) => out.flush(),
+            &mut Sink::Plain(ref mut file) => file.flush(),
+            &mut Sink::Gzip(ref mut enc) => enc.flush(),
+            &mut Sink::Zstd(ref mut enc) => enc.flush(),
+        }
+    }
+}
+
+/// Splits a plan into `shards` parts. Jobs connected by dependencies always land in the same
+/// shard, which is picked from the smallest job hash among them so that the assignment is stable
+/// across re-planning.
+pub fn shard(plan: Vec<JobInstance>, shards: usize) -> Vec<Vec<JobInstance>> {
+    fn find(parent: &mut Vec<usize>, i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[i] = root;
+        root
+    }
+
+    let position = plan.iter()
+        .enumerate()
+        .filter_map(|(i, inst)| inst.id.map(|id| (id, i)))
+        .collect::<HashMap<_, _>>();
+    let mut parent = (0..plan.len()).collect::<Vec<_>>();
+    for (i, inst) in plan.iter().enumerate() {
+        for dep in &inst.depends {
+            if let Some(&j) = position.get(dep) {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let assignment = {
+        let mut key: HashMap<usize, &str> = HashMap::new();
+        for (i, inst) in plan.iter().enumerate() {
+            let root = find(&mut parent, i);
+            let entry = key.entry(root).or_insert(inst.hash.as_str());
+            if inst.hash.as_str() < *entry {
+                *entry = inst.hash.as_str();
+            }
+        }
+
+        (0..plan.len())
+            .map(|i| {
+                let root = find(&mut parent, i);
+                // the leading 60 bits of the hash are plenty to spread components evenly
+                u64::from_str_radix(&key[&root][..15], 16).unwrap_or(0) as usize % shards
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut out = (0..shards).map(|_| vec![]).collect::<Vec<_>>();
+    for (inst, shard) in plan.into_iter().zip(assignment) {
+        out[shard].push(inst);
+    }
+    out
+}
+
+/// Inserts `-<index>` before the extensions of `path`, e.g. `plan.jsonl.gz` becomes
+/// `plan-3.jsonl.gz`.
+pub fn shard_path(path: &str, index: usize) -> String {
+    let name_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match path[name_start..].find('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}-{}{}", &path[..dot], index, &path[dot..])
+        }
+        _ => format!("{}-{}", path, index),
+    }
+}
+
+/// A record of a plan file. The header of each program comes before the first of its jobs.
+#[derive(Debug)]
+pub enum PlanEntry {
+    Program(ProgramHeader),
+    Job(JobInstance),
+}
+
+/// Key of the object that wraps a program header, telling it apart from a job.
+const HEADER_KEY: &'static str = "program_header";
+
+/// Writes a plan one job at a time.
+pub struct PlanWriter<W: Write> {
+    writer: W,
+    format: PlanFormat,
+}
+
+impl<W: Write> PlanWriter<W> {
+    pub fn new(writer: W, format: PlanFormat) -> PlanWriter<W> {
+        PlanWriter {
+            writer: writer,
+            format: format,
+        }
+    }
+
+    pub fn write(&mut self, job: &JobInstance) -> Result<()> {
+        let value = serde_json::to_value(job)?;
+        self.write_record(&value)
+    }
+
+    pub fn write_header(&mut self, header: &ProgramHeader) -> Result<()> {
+        let mut record = Map::new();
+        record.insert(HEADER_KEY.to_string(), serde_json::to_value(header)?);
+        self.write_record(&Value::Object(record))
+    }
+
+    pub fn write_entry(&mut self, entry: &PlanEntry) -> Result<()> {
+        match *entry {
+            PlanEntry::Program(ref header) => self.write_header(header),
+            PlanEntry::Job(ref job) => self.write(job),
+        }
+    }
+
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        match self.format {
+            PlanFormat::Json => {
+                serde_json::to_writer(&mut self.writer, record)?;
+                self.writer.write_all(b"\n")?;
+            }
+            PlanFormat::MsgPack => write_value(&mut self.writer, record)?,
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes a JSON value as MessagePack. Jobs go through `Value` so that the untagged parameter
+/// values are stored the same way in both formats.
+fn write_value<W: Write>(wr: &mut W, value: &Value) -> Result<()> {
+    match *value {
+        Value::Null => rmp::encode::write_nil(wr)?,
+        Value::Bool(b) => rmp::encode::write_bool(wr, b)?,
+        Value::Number(ref n) => {
+            if let Some(n) = n.as_u64() {
+                rmp::encode::write_uint(wr, n)?;
+            } else if let Some(n) = n.as_i64() {
+                rmp::encode::write_sint(wr, n)?;
+            } else {
+                rmp::encode::write_f64(wr, n.as_f64().unwrap_or(0.0))?;
+            }
+        }
+        Value::String(ref s) => rmp::encode::write_str(wr, s)?,
+        Value::Array(ref items) => {
+            rmp::encode::write_array_len(wr, items.len() as u32)?;
+            for item in items {
+                write_value(wr, item)?;
+            }
+        }
+        Value::Object(ref map) => {
+            rmp::encode::write_map_len(wr, map.len() as u32)?;
+            for (key, item) in map {
+                rmp::encode::write_str(wr, key)?;
+                write_value(wr, item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the big-endian unsigned integer of `len` bytes that follows a marker.
+fn read_uint<R: Read>(rd: &mut R, len: usize) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    rd.read_exact(&mut buf[8 - len..])?;
+    Ok(buf.iter().fold(0, |n, &b| n << 8 | b as u64))
+}
+
+/// Reads back a value written by `write_value`.
+fn read_value<R: Read>(rd: &mut R) -> Result<Value> {
+    let marker = rmp::decode::read_marker(rd).map_err(|e| e.0)?;
+    Ok(match marker {
+        Marker::Null => Value::Null,
+        Marker::True => Value::Bool(true),
+        Marker::False => Value::Bool(false),
+        Marker::FixPos(n) => Value::from(n as u64),
+        Marker::U8 => Value::from(read_uint(rd, 1)?),
+        Marker::U16 => Value::from(read_uint(rd, 2)?),
+        Marker::U32 => Value::from(read_uint(rd, 4)?),
+        Marker::U64 => Value::from(read_uint(rd, 8)?),
+        Marker::FixNeg(n) => Value::from(n as i64),
+        Marker::I8 => Value::from(read_uint(rd, 1)? as u8 as i8 as i64),
+        Marker::I16 => Value::from(read_uint(rd, 2)? as u16 as i16 as i64),
+        Marker::I32 => Value::from(read_uint(rd, 4)? as u32 as i32 as i64),
+        Marker::I64 => Value::from(read_uint(rd, 8)? as i64),
+        Marker::F32 => Value::from(f32::from_bits(read_uint(rd, 4)? as u32) as f64),
+        Marker::F64 => Value::from(f64::from_bits(read_uint(rd, 8)?)),
+        Marker::FixStr(_) | Marker::Str8 | Marker::Str16 | Marker::Str32 => {
+            Value::String(read_str(rd, marker)?)
+        }
+        Marker::FixArray(len) => read_array(rd, len as u64)?,
+        Marker::Array16 => {
+            let len = read_uint(rd, 2)?;
+            read_array(rd, len)?
+        }
+        Marker::Array32 => {
+            let len = read_uint(rd, 4)?;
+            read_array(rd, len)?
+        }
+        Marker::FixMap(len) => read_map(rd, len as u64)?,
+        Marker::Map16 => {
+            let len = read_uint(rd, 2)?;
+            read_map(rd, len)?
+        }
+        Marker::Map32 => {
+            let len = read_uint(rd, 4)?;
+            read_map(rd, len)?
+        }
+        _ => return Err(rmp::decode::ValueReadError::TypeMismatch(marker).into()),
+    })
+}
+
+fn read_str<R: Read>(rd: &mut R, marker: Marker) -> Result<String> {
+    let len = match marker {
+        Marker::FixStr(len) => len as u64,
+        Marker::Str8 => read_uint(rd, 1)?,
+        Marker::Str16 => read_uint(rd, 2)?,
+        Marker::Str32 => read_uint(rd, 4)?,
+        _ => return Err(rmp::decode::ValueReadError::TypeMismatch(marker).into()),
+    };
+    let mut buf = vec![0; len as usize];
+    rd.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+}
+
+fn read_array<R: Read>(rd: &mut R, len: u64) -> Result<Value> {
+    (0..len).map(|_| read_value(rd)).collect::<Result<Vec<_>>>().map(Value::Array)
+}
+
+fn read_map<R: Read>(rd: &mut R, len: u64) -> Result<Value> {
+    let mut map = Map::new();
+    for _ in 0..len {
+        let marker = rmp::decode::read_marker(rd).map_err(|e| e.0)?;
+        let key = read_str(rd, marker)?;
+        map.insert(key, read_value(rd)?);
+    }
+    Ok(Value::Object(map))
+}
+
+/// Tells the header of a program from a job.
+fn entry(record: Value) -> Result<PlanEntry> {
+    if let Some(header) = record.get(HEADER_KEY) {
+        return Ok(PlanEntry::Program(serde_json::from_value(header.clone())?));
+    }
+    Ok(PlanEntry::Job(serde_json::from_value(record)?))
+}
+
+/// Streams the entries of a plan back out of a reader.
+pub struct PlanReader<R: Read> {
+    reader: BufReader<R>,
+    format: PlanFormat,
+}
+
+impl<R: Read> PlanReader<R> {
+    pub fn new(reader: R, format: PlanFormat) -> PlanReader<R> {
+        PlanReader {
+            reader: BufReader::new(reader),
+            format: format,
+        }
+    }
+}
+
+impl<R: Read> Iterator for PlanReader<R> {
+    type Item = Result<PlanEntry>;
+
+    fn next(&mut self) -> Option<Result<PlanEntry>> {
+        match self.format {
+            PlanFormat::Json => {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    match self.reader.read_line(&mut line) {
+                        Err(e) => return Some(Err(e.into())),
+                        Ok(0) => return None,
+                        Ok(_) if line.trim().is_empty() => continue,
+                        Ok(_) => {
+                            return Some(serde_json::from_str(&line)
+                                .map_err(Into::into)
+                                .and_then(entry))
+                        }
+                    }
+                }
+            }
+            PlanFormat::MsgPack => {
+                let at_eof = match self.reader.fill_buf() {
+                    Ok(buf) => buf.is_empty(),
+                    Err(e) => return Some(Err(e.into())),
+                };
+                if at_eof {
+                    return None;
+                }
+                Some(read_value(&mut self.reader).and_then(entry))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use errors::Result;
+    use structs::*;
+    use serde_yaml;
+    use std::fs::File;
+
+    #[test]
+    fn format_from_path() {
+        assert!(PlanFormat::from_path("plan.json") == PlanFormat::Json);
+        assert!(PlanFormat::from_path("plan.msgpack.zst") == PlanFormat::MsgPack);
+        assert!(Compression::from_path("plan.msgpack.zst") == Compression::Zstd);
+        assert!(Compression::from_path("plan.jsonl.gz") == Compression::Gzip);
+        assert!(Compression::from_path("plan.jsonl") == Compression::None);
+    }
+
+    #[test]
+    fn shard_keeps_dependencies() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+        let plan = exp.plan(&hashmap!{
+                "interdict".to_string() => prog,
+                "interdict-validate".to_string() => validate,
+            })
+            .unwrap();
+
+        let shards = shard(plan, 4);
+        assert!(shards.iter().map(|s| s.len()).sum::<usize>() == 660);
+        for s in &shards {
+            let ids = s.iter().map(|inst| inst.id).collect::<Vec<_>>();
+            assert!(s.iter().all(|inst| inst.depends.iter().all(|dep| ids.contains(&Some(*dep)))));
+        }
+    }
+
+    #[test]
+    fn shard_paths() {
+        assert!(shard_path("out/plan.jsonl.gz", 3) == "out/plan-3.jsonl.gz");
+        assert!(shard_path("plan", 0) == "plan-0");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<name> <k> <ratio> <shift> <exact> <files> <graph>'
+            outputs:
+              result:
+                pattern: 'ratio (?P<ratio>[0-9.]+) after (?P<rounds>[0-9]+) rounds'
+                captures: {ratio: float, rounds: uint}
+            exit_codes:
+              -9: {outcome: killed, failure: true, retry: true}
+            fields:
+              name: {type: str}
+              k: {type: uint}
+              ratio: {type: float}
+              shift: {type: float}
+              exact: {type: bool}
+              files: {type: {list: path}}
+              graph: {type: path}
+            ")
+            .unwrap();
+        let exp: Experiment = serde_yaml::from_str("
+            jobs:
+              - run: est
+                priority: -3
+                env: {OMP_PROC_BIND: close}
+                parameters:
+                  name: a b
+                  k: [1, 18446744073709551615]
+                  ratio: 0.1
+                  shift: -2.5
+                  exact: true
+                  files: [[a.bin, b.bin]]
+                  graph: {value: data/huge-v3.bin, label: huge}
+            ")
+            .unwrap();
+        let header = prog.header();
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog }).unwrap();
+        assert!(plan.len() == 2);
+
+        for &format in &[PlanFormat::Json, PlanFormat::MsgPack] {
+            let mut buf = vec![];
+            {
+                let mut writer = PlanWriter::new(&mut buf, format);
+                writer.write_header(&header).unwrap();
+                for job in &plan {
+                    writer.write(job).unwrap();
+                }
+            }
+
+            let mut read = PlanReader::new(&buf[..], format).collect::<Result<Vec<_>>>().unwrap();
+            match read.remove(0) {
+                PlanEntry::Program(ref read) => assert!(read == &header),
+                entry => panic!("expected the program header, got {:?}", entry),
+            }
+            assert!(read.len() == plan.len());
+            for (a, b) in read.iter().zip(&plan) {
+                match *a {
+                    PlanEntry::Job(ref a) => {
+                        assert!(serde_json::to_value(a).unwrap() ==
+                                serde_json::to_value(b).unwrap());
+                    }
+                    ref entry => panic!("expected a job, got {:?}", entry),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_plan_without_hashes() {
+        // plans written before jobs were hashed have no `hash`
+        let line = r#"{"id": 0, "program": "est", "command": "est 1", "params": {"k": 1},
+                       "log": null, "depends": [], "threads": 1, "priority": 0,
+                       "runtime": null, "memory": null}"#;
+        let read = PlanReader::new(line.replace('\n', " ").as_bytes(), PlanFormat::Json)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        match read[0] {
+            PlanEntry::Job(ref job) => assert!(job.program == "est" && job.hash.is_empty()),
+            ref entry => panic!("expected a job, got {:?}", entry),
+        }
+    }
+}
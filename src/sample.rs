@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+/// A small seeded generator (SplitMix64). Sampled designs must come out the same on every machine
+/// and toolchain for a given seed, which rules out the hashers in std.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// FNV-1a, a string hash that is stable across platforms and releases.
+fn fnv(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The seed for repetition `index` of a seed group. Seeds fit in 31 bits, so that programs
+/// reading them into any signed or unsigned 32-bit integer see the same value.
+pub fn group_seed(group: &str, index: usize) -> usize {
+    let mut rng = Rng::new(fnv(group) ^ (index as u64).wrapping_mul(0x9E3779B97F4A7C15));
+    (rng.next_u64() >> 33) as usize
+}
+
+/// Picks `k` distinct indices out of `0..n` (all of them if `k >= n`), in increasing order.
+pub fn sample_indices(n: usize, k: usize, rng: &mut Rng) -> Vec<usize> {
+    if k >= n {
+        return (0..n).collect();
+    }
+
+    // Floyd's algorithm: one draw per sample, however large n is
+    let mut chosen = HashSet::new();
+    for j in (n - k)..n {
+        let t = rng.below(j + 1);
+        if !chosen.insert(t) {
+            chosen.insert(j);
+        }
+    }
+    let mut chosen = chosen.into_iter().collect::<Vec<_>>();
+    chosen.sort();
+    chosen
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sample_is_reproducible() {
+        let a = sample_indices(1000000, 50, &mut Rng::new(42));
+        let b = sample_indices(1000000, 50, &mut Rng::new(42));
+        assert!(a == b);
+        assert!(a.len() == 50 && a.windows(2).all(|w| w[0] < w[1]));
+        assert!(sample_indices(5, 10, &mut Rng::new(1)) == vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn group_seeds() {
+        assert!(group_seed("cmp", 3) == group_seed("cmp", 3));
+        assert!(group_seed("cmp", 3) != group_seed("cmp", 4));
+        assert!(group_seed("cmp", 3) != group_seed("other", 3));
+        assert!(group_seed("cmp", 3) < 1 << 31);
+    }
+}
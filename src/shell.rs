@@ -0,0 +1,105 @@
+use std::iter::repeat;
+
+/// The shell that runs planned commands, which decides how values are written into them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Shell {
+    /// sh and friends. Values are inserted as they are, as they always have been, so that specs
+    /// may rely on the shell to expand them.
+    Posix,
+    /// cmd.exe, with arguments read the way the Microsoft C runtime splits them.
+    Cmd,
+    #[serde(rename = "powershell")]
+    PowerShell,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::Posix
+    }
+}
+
+impl Shell {
+    /// Writes `value` as a single argument. Paths get the separators of the shell's platform.
+    pub fn quote(&self, value: &str, path: bool) -> String {
+        let value = if path && *self != Shell::Posix {
+            value.replace('/', "\\")
+        } else {
+            value.to_string()
+        };
+
+        match *self {
+            Shell::Posix => value,
+            Shell::Cmd => {
+                if !value.is_empty() && !value.contains(|c| " \t\"&|<>^%!".contains(c)) {
+                    return value;
+                }
+                // backslashes are only special before a quote, where each must be doubled. cmd
+                // expands variables within quotes too, so % and ! are escaped outside of them,
+                // e.g. %PATH% is written as ^%"PATH"^%
+                let mut quoted = String::new();
+                let mut open = false;
+                let mut backslashes = 0;
+                for c in value.chars() {
+                    if c == '%' || c == '!' {
+                        if open {
+                            quoted.extend(repeat('\\').take(backslashes));
+                            quoted.push('"');
+                            open = false;
+                        }
+                        backslashes = 0;
+                        quoted.push('^');
+                        quoted.push(c);
+                        continue;
+                    }
+
+                    if !open {
+                        quoted.push('"');
+                        open = true;
+                    }
+                    match c {
+                        '\\' => backslashes += 1,
+                        '"' => {
+                            quoted.extend(repeat('\\').take(backslashes + 1));
+                            backslashes = 0;
+                        }
+                        _ => backslashes = 0,
+                    }
+                    quoted.push(c);
+                }
+                if open {
+                    quoted.extend(repeat('\\').take(backslashes));
+                    quoted.push('"');
+                } else if quoted.is_empty() {
+                    quoted.push_str("\"\"");
+                }
+                quoted
+            }
+            Shell::PowerShell => {
+                if !value.is_empty() &&
+                   value.chars().all(|c| c.is_alphanumeric() || "_-.,:\\/=+".contains(c)) {
+                    return value;
+                }
+                format!("'{}'", value.replace('\'', "''"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quote_values() {
+        assert!(Shell::Posix.quote("data/a b.bin", true) == "data/a b.bin");
+        assert!(Shell::Cmd.quote("data/a.bin", true) == "data\\a.bin");
+        assert!(Shell::Cmd.quote("C:/my data/", true) == "\"C:\\my data\\\\\"");
+        assert!(Shell::Cmd.quote("say \"hi\"", false) == "\"say \\\"hi\\\"\"");
+        assert!(Shell::Cmd.quote("", false) == "\"\"");
+        assert!(Shell::Cmd.quote("%PATH%", false) == "^%\"PATH\"^%");
+        assert!(Shell::Cmd.quote("C:/a b/%USER%!", true) == "\"C:\\a b\\\\\"^%\"USER\"^%^!");
+        assert!(Shell::PowerShell.quote("it's", false) == "'it''s'");
+        assert!(Shell::PowerShell.quote("0.5", false) == "0.5");
+    }
+}
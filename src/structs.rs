@@ -1,10 +1,113 @@
 use errors::*;
+use expr::Expr;
+use values;
+use units::{self, DateTime};
+use shell::Shell;
+use which::which;
+use sample::{group_seed, sample_indices, Rng};
 
-use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::ops::Index;
+use std::path::{Path, PathBuf};
 use std::string::ToString;
+use std::sync::Arc;
+use std::time::SystemTime;
+use template::{Segment, Template};
+use cache::{cache_key, PlanCache};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{MapVisitor, SeqVisitor, Visitor};
+use serde::de::impls::IgnoredAny;
+use serde_json;
 use regex::Regex;
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone)]
+/// Longest argument accepted at plan time. Linux refuses any single argument longer than this
+/// (MAX_ARG_STRLEN), so a command with a longer one is almost certainly going to fail.
+pub const MAX_ARG_STRLEN: usize = 131072;
+
+/// Longest command accepted at plan time, the usual Linux limit on the arguments and environment
+/// of a process together (ARG_MAX).
+pub const ARG_MAX: usize = 2097152;
+
+/// Values longer than this are spilled to a response file if their field accepts one.
+const SPILL_LENGTH: usize = 4096;
+
+/// Placeholder standing in for the binary of a program with one per platform, filled in by the
+/// tool running the plan.
+pub const BIN_PLACEHOLDER: &'static str = "<bin>";
+
+/// Placeholder in a program's format marking where optioned fields are inserted.
+pub const OPTIONS_ANCHOR: &'static str = "options";
+
+/// Name of the thread count of an instance in formulas and templates. A program field with this
+/// name is filled from the `threads` setting of the job rather than from its parameters.
+pub const THREADS_NAME: &'static str = "threads";
+
+/// Name standing for the hash of a job's parameters in formats and labels, as in `<hash(params)>`.
+pub const PARAMS_NAME: &'static str = "params";
+
+/// Version of the spec layout this build reads. Specs without a `spec_version` are taken to be
+/// version 1, the layout from before versioning.
+pub const SPEC_VERSION: u32 = 1;
+
+/// Where spilled values are written when no `response_dir` is set, in the system's temp dir.
+const RESPONSE_FILE_DIR: &'static str = "waluigi-args";
+
+/// The directory response files go to when no `response_dir` is set.
+pub fn default_response_dir() -> PathBuf {
+    env::temp_dir().join(RESPONSE_FILE_DIR)
+}
+
+/// The response files of the values spilled while rendering commands, by path.
+///
+/// Rendering only decides where each file goes. They are written with `write` once the plan
+/// referring to them is, so e.g. estimating a plan leaves nothing behind.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ResponseFiles {
+    dir: PathBuf,
+    files: BTreeMap<String, String>,
+}
+
+impl ResponseFiles {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> ResponseFiles {
+        ResponseFiles {
+            dir: dir.into(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a file holding `contents` and returns its path. Files are named by the SHA-256 of
+    /// their contents, so re-planning does not pile up copies.
+    fn add(&mut self, contents: String) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input_str(&contents);
+        let path = self.dir
+            .join(format!("{}.args", hasher.result_str()))
+            .to_string_lossy()
+            .into_owned();
+        self.files.insert(path.clone(), contents);
+        path
+    }
+
+    /// Writes the files, creating their directory if needed.
+    pub fn write(&self) -> Result<()> {
+        if !self.files.is_empty() {
+            fs::create_dir_all(&self.dir)?;
+        }
+        for (path, contents) in &self.files {
+            File::create(path)?.write_all(contents.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum FieldType {
     Str,
@@ -13,18 +116,118 @@ pub enum FieldType {
     UInt,
     Float,
     Bool,
+    /// A length of time such as `5m`, `2h30m` or `00:30:00`, or a number of seconds.
+    Duration,
+    /// An RFC 3339 timestamp such as `2017-03-01T12:00:00Z`, or a date.
+    Datetime,
+    /// A number of bytes, possibly with a unit such as `512M` or `2G`.
+    Size,
+    /// A reference to a secret such as an API key: `env:<variable>` or `keyring:<name>`. Plans
+    /// only ever hold the reference, see `secret_placeholder`.
+    Secret,
+    /// Any number of values of another type, written `{list: <type>}`, e.g. several input files.
+    /// Since a list of values in parameters is swept over, a single list value is written inside
+    /// one, as in `files: [[a.bin, b.bin]]`.
+    List(Box<FieldType>),
+}
+
+/// Whether `text` is a reference to a secret, rather than a secret itself.
+fn is_secret_ref(text: &str) -> bool {
+    if text.starts_with("env:") {
+        let name = &text[4..];
+        !name.is_empty() && !name.starts_with(|c: char| c.is_digit(10)) &&
+        name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    } else {
+        text.starts_with("keyring:") && text.len() > "keyring:".len()
+    }
+}
+
+/// The placeholder written into commands in place of a secret, for the tool running the plan to
+/// fill in just before the command runs.
+pub fn secret_placeholder(reference: &str) -> String {
+    format!("<secret:{}>", reference)
 }
 
 impl FieldType {
     pub fn matches(&self, data: &FieldData) -> bool {
         match data {
-            &FieldData::Str(_) => self == &FieldType::Str || self == &FieldType::Path,
-            &FieldData::UInt(_) => self == &FieldType::UInt,
+            &FieldData::Str(ref s) => {
+                self == &FieldType::Str || self == &FieldType::Path ||
+                (self == &FieldType::Duration && units::parse_duration(s).is_some()) ||
+                (self == &FieldType::Datetime && DateTime::parse(s).is_some()) ||
+                (self == &FieldType::Size && units::parse_size(s).is_some()) ||
+                (self == &FieldType::Secret && is_secret_ref(s))
+            }
+            &FieldData::UInt(_) => {
+                self == &FieldType::UInt || self == &FieldType::Float ||
+                self == &FieldType::Duration || self == &FieldType::Size
+            }
             &FieldData::Float(f) => {
-                self == &FieldType::Float || (self == &FieldType::UInt && f.trunc() == f)
+                self == &FieldType::Float || (self == &FieldType::UInt && f.trunc() == f) ||
+                (self == &FieldType::Duration && f >= 0.0)
             }
             &FieldData::Bool(_) => self == &FieldType::Bool,
             &FieldData::Future => self == &FieldType::Str,
+            &FieldData::List(ref items) => {
+                match self {
+                    &FieldType::List(ref inner) => items.iter().all(|item| inner.matches(item)),
+                    _ => false,
+                }
+            }
+            &FieldData::Labeled { ref value, .. } => self.matches(value),
+        }
+    }
+
+    /// Writes a matching datum the way fields of this type render it. Lists are written element
+    /// by element, see `Field::fill_in`.
+    fn text(&self, datum: &FieldData, render: Option<&str>) -> Option<String> {
+        match *self {
+            FieldType::Duration => {
+                let secs = match datum {
+                    &FieldData::Str(ref s) => units::parse_duration(s),
+                    datum => datum.as_float(),
+                };
+                secs.and_then(|secs| units::render_duration(secs, render))
+            }
+            FieldType::Datetime => {
+                match datum {
+                    &FieldData::Str(ref s) => {
+                        DateTime::parse(s).and_then(|dt| units::render_datetime(&dt, render))
+                    }
+                    _ => None,
+                }
+            }
+            FieldType::Size => {
+                match (datum, render) {
+                    (&FieldData::Str(ref s), Some("original")) => {
+                        units::parse_size(s).map(|_| s.trim().to_string())
+                    }
+                    (&FieldData::Str(ref s), _) => {
+                        units::parse_size(s).and_then(|bytes| units::render_size(bytes, render))
+                    }
+                    (&FieldData::UInt(bytes), Some("original")) => Some(bytes.to_string()),
+                    (&FieldData::UInt(bytes), _) => units::render_size(bytes as u64, render),
+                    _ => None,
+                }
+            }
+            FieldType::Secret => {
+                match datum {
+                    &FieldData::Str(ref s) => Some(secret_placeholder(s)),
+                    _ => None,
+                }
+            }
+            FieldType::List(_) => None,
+            _ if render.is_some() => None,
+            _ => Some(datum.to_string()),
+        }
+    }
+
+    /// The datum to show in messages, which for secrets is never the value itself.
+    fn redact(&self, datum: &FieldData) -> FieldData {
+        match *self {
+            FieldType::Secret => FieldData::Str("<redacted>".to_string()),
+            FieldType::List(ref inner) => inner.redact(datum),
+            _ => datum.clone(),
         }
     }
 
@@ -35,6 +238,7 @@ impl FieldType {
                 self.matches(to) && self.matches(step)
             }
             &FieldSetting::List(ref data) => data.iter().all(|datum| self.matches(datum)),
+            &FieldSetting::File { .. } => false,
             &FieldSetting::Value(ref v) => self.matches(v),
         }
     }
@@ -54,16 +258,42 @@ impl Default for BatchType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Field {
     #[serde(rename = "type")]
     dtype: FieldType,
     #[serde(default)]
     aka: Vec<String>,
-    option: Option<String>,
+    option: Option<Template>,
     #[serde(default)]
     batch: BatchType,
+    /// Template such as `@<file>` used in place of values too long to pass on the command line.
+    response_file: Option<Template>,
+    /// How values are written into commands, for types with more than one way: `seconds` or
+    /// `hms` for durations, a strftime-style format such as `%Y%m%d` for datetimes, and `bytes`,
+    /// `kib`, `mib`, `gib` or `original` (as written) for sizes.
+    render: Option<String>,
+    /// Joins the elements of a list into one argument, e.g. with `,`. Otherwise each element is
+    /// its own argument, repeating the option if the field has one.
+    separator: Option<String>,
+    /// Regular expression that the whole of every value of a str or path field must match.
+    pattern: Option<String>,
+    /// Bounds on the values of a uint or float field, inclusive unless marked exclusive.
+    min: Option<f64>,
+    max: Option<f64>,
+    #[serde(default)]
+    min_exclusive: bool,
+    #[serde(default)]
+    max_exclusive: bool,
+    /// Where the options of this field go among the others: lower weights come first, and fields
+    /// without an option ignore it.
+    #[serde(default)]
+    order: i64,
+    /// Values of other parameters that the field is only used with, e.g. `{method: annealing}`.
+    /// Elsewhere it is neither required nor rendered, and may not be given.
+    #[serde(default)]
+    when: BTreeMap<String, FieldSetting>,
 }
 
 impl Field {
@@ -71,569 +301,4712 @@ impl Field {
         self.dtype.matches(&datum)
     }
 
-    pub fn fill_with(&self, datum: &FieldData) -> Result<String> {
-        if self.matches(datum) {
-            if let Some(ref opt) = self.option {
-                match datum {
-                    &FieldData::Bool(false) => Ok("".to_string()),
-                    &FieldData::Bool(true) => Ok(opt.clone()),
-                    _ => {
-                        let rep: &str = &datum.to_string();
-                        Ok(Regex::new(r"<.+?>")
-                            .unwrap()
-                            .replace(&opt, rep)
-                            .to_string())
-                    }
-                }
-            } else {
-                Ok(datum.to_string())
+    /// Whether the field is used with the parameters `params`.
+    fn active(&self, params: &Params) -> Result<bool> {
+        for (name, allowed) in &self.when {
+            let datum = match params.get(name) {
+                Some(datum) => datum,
+                None => return Ok(false),
+            };
+            if !allows(&allowed.vectorize(name)?, datum) {
+                return Ok(false);
             }
-        } else {
-            Err(ErrorKind::FieldMismatch(self.dtype, datum.clone()).into())
         }
+        Ok(true)
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
-#[serde(deny_unknown_fields)]
-pub struct Output {
-    msg: String,
-    #[serde(default)]
-    aka: Vec<String>,
-}
+    /// Whether the field is used with `any` or with every combination of the settings `params`.
+    fn active_with(&self, params: &HashMap<String, FieldSetting>, any: bool) -> Result<bool> {
+        for (name, allowed) in &self.when {
+            let data = match params.get(name) {
+                Some(param) => param.vectorize(name)?,
+                None => return Ok(false),
+            };
+            let allowed = allowed.vectorize(name)?;
+            let active = if any {
+                data.iter().any(|datum| allows(&allowed, datum))
+            } else {
+                data.iter().all(|datum| allows(&allowed, datum))
+            };
+            if !active {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct Program {
-    pub name: String,
-    bin: String,
-    format: String,
-    outputs: HashMap<String, Output>,
-    fields: HashMap<String, Field>,
-}
+    /// Describes the `when` conditions, e.g. `method is annealing or tempering`.
+    fn condition(&self) -> Result<String> {
+        let mut conditions = vec![];
+        for (name, allowed) in &self.when {
+            let values = allowed.vectorize(name)?
+                .iter()
+                .map(|datum| datum.to_string())
+                .collect::<Vec<_>>();
+            conditions.push(format!("{} is {}", name, values.join(" or ")));
+        }
+        Ok(conditions.join(" and "))
+    }
 
-impl Program {
-    pub fn cmd(&self, params: &HashMap<String, FieldData>) -> Result<String> {
-        let mut fmt = format!("{} {}", self.bin, self.format);
-        for (field, datum) in params {
-            if self.fields.contains_key(field) && datum != &FieldData::Future &&
-               self.fields[field].matches(&datum) {
-                if self.fields[field].option.is_none() {
-                    let fname = format!("<{}>", field);
-                    fmt = fmt.replace(&fname, &self.fields[field].fill_with(datum)?);
-                } else {
-                    fmt.push_str(" ");
-                    fmt.push_str(&self.fields[field].fill_with(datum)?);
-                }
+    /// Describes the bound that `value` falls outside of, if any.
+    fn violated_bound(&self, value: f64) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), _) if self.min_exclusive && value <= min => {
+                Some(format!("greater than {}", min))
             }
+            (Some(min), _) if value < min => Some(format!("at least {}", min)),
+            (_, Some(max)) if self.max_exclusive && value >= max => {
+                Some(format!("less than {}", max))
+            }
+            (_, Some(max)) if value > max => Some(format!("at most {}", max)),
+            _ => None,
         }
-        return Ok(fmt);
     }
 
-    pub fn validate_parameters(&self, params: &HashMap<String, FieldSetting>) -> Result<()> {
-        // every field must either be filled or be optional (as indicated by the option: foo field
-        // on the field object)
-        for (field, details) in &self.fields {
-            if !params.contains_key(field) && details.option.is_none() {
-                return Err(ErrorKind::MissingParameter(field.clone(), self.name.clone()).into());
+    /// Checks the values of a parameter against the field's constraints, beyond its type.
+    fn check(&self, name: &str, data: &[FieldData]) -> Result<()> {
+        // invalid patterns are reported by Program::validate
+        let whole = self.pattern
+            .as_ref()
+            .and_then(|pattern| Regex::new(&format!("^(?:{})$", pattern)).ok());
+        for datum in data {
+            match (datum, whole.as_ref()) {
+                (&FieldData::Str(ref value), Some(whole)) if !whole.is_match(value) => {
+                    return Err(ErrorKind::PatternMismatch(name.to_string(),
+                                                          value.clone(),
+                                                          self.pattern.clone().unwrap())
+                        .into());
+                }
+                (&FieldData::UInt(_), _) |
+                (&FieldData::Float(_), _) => {
+                    let value = datum.as_float().unwrap();
+                    if let Some(bound) = self.violated_bound(value) {
+                        return Err(ErrorKind::OutOfRange(name.to_string(), value, bound).into());
+                    }
+                }
+                (&FieldData::List(ref items), _) => self.check(name, items)?,
+                (&FieldData::Labeled { ref value, .. }, _) => {
+                    self.check(name, &[(**value).clone()])?
+                }
+                _ => {}
             }
+        }
+        Ok(())
+    }
 
-            if !params.contains_key(field) {
-                continue;
+    /// Adds `value` to `files` and returns the filled `response_file` template if the value is too
+    /// long and the field accepts one; otherwise returns `value` unchanged, unless it is too long
+    /// to be passed as one argument at all.
+    fn spill(&self, value: String, files: &mut ResponseFiles) -> Result<String> {
+        match self.response_file {
+            Some(ref template) if value.len() > SPILL_LENGTH => {
+                let path = files.add(value);
+                Ok(template.render(|_| Some(path.clone())))
             }
-
-            let ref param = params[field];
-            if !details.dtype.matches_setting(param) {
-                return Err(ErrorKind::InvalidParameterSetting(field.clone(),
-                                                              param.clone(),
-                                                              details.dtype)
-                    .into());
+            _ if value.len() > MAX_ARG_STRLEN => {
+                let start = value.chars().take(40).collect();
+                Err(ErrorKind::ArgumentTooLong(start, value.len()).into())
             }
+            _ => Ok(value),
         }
+    }
 
-        Ok(())
+    /// Fills the field for a POSIX shell, see `fill_in`.
+    #[cfg(test)]
+    pub fn fill_with(&self, datum: &FieldData) -> Result<String> {
+        self.fill_in(datum, Shell::Posix, &mut ResponseFiles::new(default_response_dir()))
     }
 
-    pub fn validate_parameter_data(&self, params: &HashMap<String, FieldData>) -> Result<()> {
-        // every field must either be filled or be optional (as indicated by the option: foo field
-        // on the field object)
-        for (field, details) in &self.fields {
-            if !params.contains_key(field) && details.option.is_none() {
-                return Err(ErrorKind::MissingParameter(field.clone(), self.name.clone()).into());
-            }
+    /// Renders `datum` into the field, quoting the value for `shell` and adding the values it
+    /// spills to `files`.
+    pub fn fill_in(&self,
+                   datum: &FieldData,
+                   shell: Shell,
+                   files: &mut ResponseFiles)
+                   -> Result<String> {
+        let mismatch = || {
+            Error::from(ErrorKind::FieldMismatch(self.dtype.clone(), self.dtype.redact(datum)))
+        };
+        if !self.matches(datum) {
+            return Err(mismatch());
+        }
+        match (self.option.as_ref(), datum) {
+            (Some(_), &FieldData::Bool(false)) => return Ok("".to_string()),
+            (Some(opt), &FieldData::Bool(true)) => return Ok(opt.to_string()),
+            _ => {}
+        }
 
-            if !params.contains_key(field) {
-                continue;
+        let render = self.render.as_ref().map(|r| r.as_str());
+        let (dtype, texts) = match (&self.dtype, datum) {
+            (&FieldType::List(ref inner), &FieldData::List(ref items)) => {
+                (&**inner,
+                 items.iter().map(|item| inner.text(item, render)).collect::<Option<Vec<_>>>())
             }
+            (dtype, datum) => (dtype, dtype.text(datum, render).map(|text| vec![text])),
+        };
+        let texts = texts.ok_or_else(&mismatch)?;
+        let texts = match self.separator {
+            Some(ref separator) => vec![texts.join(separator)],
+            None => texts,
+        };
 
-            let ref param = params[field];
-            if !details.dtype.matches(param) {
-                return Err(ErrorKind::InvalidParameterData(field.clone(),
-                                                           param.clone(),
-                                                           details.dtype)
-                    .into());
-            }
+        let mut args = vec![];
+        for text in texts {
+            let text = self.spill(text, files)?;
+            // placeholders are replaced as they are, before the shell sees them
+            let rep = if *dtype == FieldType::Secret {
+                text
+            } else {
+                shell.quote(&text, *dtype == FieldType::Path)
+            };
+            args.push(match self.option {
+                Some(ref opt) => opt.render(|_| Some(rep.clone())),
+                None => rep,
+            });
         }
+        Ok(args.join(" "))
+    }
+}
 
-        Ok(())
+/// Where a program leaves an output.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputKind {
+    /// A value printed to stdout, only known once the job has run.
+    Stdout,
+    /// A file the program writes, whose path is known when the job is planned.
+    File,
+}
+
+impl Default for OutputKind {
+    fn default() -> Self {
+        OutputKind::Stdout
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    #[serde(default)]
+    msg: String,
+    #[serde(default)]
+    aka: Vec<String>,
+    #[serde(default)]
+    kind: OutputKind,
+    /// Path of a file output, filled with the parameters of each instance, e.g.
+    /// `out/<stem(graph)>.csv`.
+    path: Option<Template>,
+    /// Regular expression matched against stdout in place of `msg`. Each of its named groups
+    /// that is listed in `captures` is a value.
+    pattern: Option<String>,
+    /// The values an output yields and their types, e.g. `{ratio: float, rounds: uint}`. By
+    /// default, there is one value named after the output.
+    #[serde(default)]
+    captures: BTreeMap<String, FieldType>,
+    /// Only the last this many lines of stdout are searched, so that long logs need not be.
+    tail: Option<usize>,
+    /// Only the lines inside the last such block of stdout are searched.
+    block: Option<Block>,
+    /// Bounds that every numeric value of the output should be within, inclusive unless marked
+    /// exclusive, e.g. `min: 0` and `max: 1` for a ratio. Values outside of them do not fail the
+    /// job, but mark its results as suspect.
+    min: Option<f64>,
+    max: Option<f64>,
+    #[serde(default)]
+    min_exclusive: bool,
+    #[serde(default)]
+    max_exclusive: bool,
+}
+
+/// A delimited part of stdout, such as the summary that a simulation prints when it is done.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Block {
+    /// Regular expression matching the line that opens the block.
+    pub begin: String,
+    /// Regular expression matching the line that closes the block. By default, the block runs to
+    /// the end of stdout.
+    pub end: Option<String>,
+}
+
+/// How the tools that run plans read the outputs of a program from its stdout.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StdoutFormat {
+    /// Lines of text, searched for the `msg` or `pattern` of each output.
+    Text,
+    /// One JSON object per line. Outputs are the objects whose `msg` matches, and their values
+    /// are the members named in `captures`.
+    JsonLines,
+}
+
+impl Default for StdoutFormat {
+    fn default() -> Self {
+        StdoutFormat::Text
     }
 }
 
+/// Estimated runtime of a single job, in seconds: either a constant or an arithmetic formula over
+/// the job's numeric parameters (and `threads`), e.g. `n * k * 1e-6`.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
-pub enum FieldData {
-    Future,
-    Str(String),
-    Float(f64),
-    UInt(usize),
-    Bool(bool),
+pub enum Runtime {
+    Constant(f64),
+    Formula(String),
 }
 
-impl FieldData {
-    pub fn unwrap_usize(&self) -> usize {
-        match self {
-            &FieldData::UInt(u) => u,
-            _ => panic!(),
-        }
-    }
+/// A resource amount of a job: a constant, or a formula over each instance's numeric parameters
+/// such as `<k> / 2`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Amount {
+    Constant(f64),
+    Formula(String),
+}
 
-    pub fn unwrap_float(&self) -> f64 {
+impl Amount {
+    /// Evaluates the amount for one instance. `threads` is available to formulas once it is
+    /// known.
+    pub fn eval(&self, params: &Params, threads: Option<usize>) -> Result<f64> {
         match self {
-            &FieldData::Float(u) => u,
-            _ => panic!(),
+            &Amount::Constant(v) => Ok(v),
+            &Amount::Formula(ref formula) => {
+                let lookup = |name: &str| if name == THREADS_NAME {
+                    threads.map(|t| t as f64)
+                } else {
+                    params.get(name).and_then(|datum| datum.as_float())
+                };
+                Expr::parse(formula)?.eval(&lookup)
+            }
         }
     }
 }
 
-impl ToString for FieldData {
-    fn to_string(&self) -> String {
-        match self {
-            &FieldData::Str(ref s) => s.clone(),
-            &FieldData::UInt(v) => v.to_string(),
-            &FieldData::Float(v) => v.to_string(),
-            &FieldData::Bool(v) => v.to_string(),
-            &FieldData::Future => "".to_string(),
+/// A pattern that ends a job early once it appears in the job's output, e.g. `diverged`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StopRule {
+    /// Regular expression matched against each line of stdout as it is written.
+    pub pattern: String,
+    /// The outcome recorded for a job stopped by this rule.
+    pub outcome: String,
+    /// Whether a job stopped by this rule failed, rather than finished early.
+    #[serde(default)]
+    pub failure: bool,
+}
+
+/// What an exit status of a program means, e.g. that `3` is an infeasible instance rather than a
+/// crash. Statuses a program does not list are failures, except 0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Outcome {
+    pub outcome: String,
+    #[serde(default)]
+    pub failure: bool,
+    /// Whether a job failing this way is worth running again, within its `retries`.
+    #[serde(default)]
+    pub retry: bool,
+}
+
+/// Shell commands run as a job changes state, with the job's parameters in their environment.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    pub on_start: Option<String>,
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+}
+
+impl Hooks {
+    /// Combines two sets of hooks, with each hook of `over` replacing the same hook of `self`.
+    pub fn merge(&self, over: &Hooks) -> Hooks {
+        Hooks {
+            on_start: over.on_start.clone().or_else(|| self.on_start.clone()),
+            on_success: over.on_success.clone().or_else(|| self.on_success.clone()),
+            on_failure: over.on_failure.clone().or_else(|| self.on_failure.clone()),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The binary of a program: a single path, or one per platform for pools of mixed machines.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
-pub enum FieldSetting {
-    Range {
-        from: FieldData,
-        to: FieldData,
-        step: FieldData,
-    },
-    List(Vec<FieldData>),
-    Value(FieldData),
+pub enum Bin {
+    Path(String),
+    /// Paths keyed by platform, such as `linux-x86_64` or `darwin-arm64`, or by a regular
+    /// expression matching host names. The tool running the plan picks one on each machine.
+    Platforms(BTreeMap<String, String>),
 }
 
-impl FieldSetting {
-    pub fn vectorize(&self) -> Vec<FieldData> {
+impl Bin {
+    /// The binary for the machine planning the experiment, if it has one.
+    fn local(&self) -> Option<&str> {
         match self {
-            &FieldSetting::Range { ref from, ref to, ref step } => {
-                let mut range = Vec::new();
-                match from {
-                    &FieldData::UInt(start) => {
-                        let end = to.unwrap_usize();
-                        let step = step.unwrap_usize();
-                        let mut cur = start;
-
-                        while cur <= end {
-                            range.push(FieldData::UInt(cur));
-                            cur += step;
-                        }
-                    }
-                    &FieldData::Float(start) => {
-                        let end = to.unwrap_float();
-                        let step = step.unwrap_float();
-                        let mut cur = start;
-
-                        while cur <= end {
-                            range.push(FieldData::Float(cur));
-                            cur += step;
-                        }
-                    }
-                    _ => unreachable!(),
-                }
-                range
+            &Bin::Path(ref path) => Some(path.as_str()),
+            &Bin::Platforms(ref paths) => {
+                let os = match env::consts::OS {
+                    "macos" => "darwin",
+                    os => os,
+                };
+                paths.get(&format!("{}-{}", os, env::consts::ARCH)).map(|path| path.as_str())
             }
-            &FieldSetting::List(ref v) => v.clone(),
-            &FieldSetting::Value(ref v) => vec![v.clone()],
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
-pub struct Job {
-    run: String,
-    parameters: HashMap<String, FieldSetting>,
-    repetitions: Option<usize>,
-    on_each: Option<Vec<String>>,
+pub struct Program {
+    pub spec_version: Option<u32>,
+    pub name: String,
+    bin: Bin,
+    format: Template,
+    outputs: HashMap<String, Output>,
+    /// How outputs are written to stdout.
+    #[serde(default)]
+    stdout: StdoutFormat,
+    fields: HashMap<String, Field>,
+    runtime: Option<Runtime>,
+    /// Memory used by one instance, in GB.
+    memory: Option<f64>,
+    /// Rules for stopping jobs early on their output, passed on to the tools that run plans.
+    #[serde(default)]
+    stop_on: Vec<StopRule>,
+    /// The outcome of each exit status, passed on to the tools that run plans.
+    #[serde(default)]
+    exit_codes: BTreeMap<i32, Outcome>,
+    /// Hooks of every job running the program, unless the experiment replaces them.
+    #[serde(default)]
+    hooks: Hooks,
+    /// Most instances of the program that may run at once, whatever the capacity, e.g. to spare
+    /// a shared license server. Passed on to the tools that run plans.
+    max_concurrent: Option<usize>,
+    /// Expected SHA-256 of `bin`, so that a stale build is never planned. Only checked for a
+    /// single binary.
+    sha256: Option<String>,
+    /// Expected SHA-256 of input files, by path.
+    #[serde(default)]
+    checksums: BTreeMap<String, String>,
+    /// Absolute path of `bin`, once found by `resolve_bin`.
+    #[serde(skip_deserializing)]
+    resolved_bin: Option<String>,
+    /// SHA-256 of the binary at `resolved_bin`.
+    #[serde(skip_deserializing)]
+    resolved_sha256: Option<String>,
 }
 
-impl Job {
-    pub fn has_depends(&self) -> bool {
-        self.on_each.is_some()
+impl Program {
+    /// Finds the binary of the program in `dirs` or on the `PATH`, failing with the locations
+    /// searched if it is missing or not executable. Programs with a binary per platform are only
+    /// checked if one is for this machine's platform.
+    pub fn resolve_bin(&mut self, dirs: &[String]) -> Result<()> {
+        let bin = match self.bin.local() {
+            Some(bin) => bin.to_string(),
+            None => return Ok(()),
+        };
+        match which(&bin, dirs) {
+            Ok(path) => {
+                self.resolved_sha256 = Some(file_sha256(&path)?);
+                self.resolved_bin = Some(path.display().to_string());
+                Ok(())
+            }
+            Err(searched) => {
+                Err(ErrorKind::BinaryNotFound(self.name.clone(),
+                                              bin,
+                                              searched.iter()
+                                                  .map(|path| path.display().to_string())
+                                                  .collect())
+                    .into())
+            }
+        }
     }
 
-    pub fn batch(&self) -> Result<Vec<HashMap<String, FieldData>>> {
-        let mut param_sets = HashMap::new();
-
-        for (field, param) in &self.parameters {
-            param_sets.insert(field.clone(), param.vectorize());
+    /// The header the program gets in plans that run it.
+    pub fn header(&self) -> ProgramHeader {
+        ProgramHeader {
+            name: self.name.clone(),
+            outputs: self.outputs.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            stdout: self.stdout,
+            stop_on: self.stop_on.clone(),
+            exit_codes: self.exit_codes
+                .iter()
+                .map(|(&code, outcome)| (code, outcome.clone()))
+                .collect(),
+            hooks: self.hooks.clone(),
+            max_concurrent: self.max_concurrent,
+            bins: match self.bin {
+                Bin::Path(_) => BTreeMap::new(),
+                Bin::Platforms(ref paths) => paths.clone(),
+            },
         }
+    }
 
-        fn prod(params: HashMap<String, Vec<FieldData>>) -> Vec<HashMap<String, FieldData>> {
-            let key = params.keys().next();
-            if let Some(key) = key {
-                let mut cl = params.clone();
-                cl.remove(key);
-                let subproducts = prod(cl);
+    /// Checks the parts of the program spec that cannot be checked while parsing it.
+    pub fn validate(&self) -> Result<()> {
+        for (name, field) in &self.fields {
+            let render = field.render.as_ref().map(|r| r.as_str());
+            let dtype = match field.dtype {
+                FieldType::List(ref inner) => &**inner,
+                ref dtype => dtype,
+            };
+            let valid = match *dtype {
+                FieldType::Duration => units::render_duration(0.0, render).is_some(),
+                FieldType::Datetime => {
+                    let epoch = DateTime::parse("1970-01-01").unwrap();
+                    units::render_datetime(&epoch, render).is_some()
+                }
+                FieldType::Size => {
+                    render == Some("original") || units::render_size(0, render).is_some()
+                }
+                _ => render.is_none(),
+            };
+            if !valid {
+                return Err(ErrorKind::InvalidRender(self.name.clone(),
+                                                    name.clone(),
+                                                    field.render.clone().unwrap_or_default(),
+                                                    field.dtype.clone())
+                    .into());
+            }
+        }
 
-                subproducts.iter()
-                    .flat_map(|prod| {
-                        params[key]
-                            .iter()
-                            .map(|datum| {
-                                let mut p = prod.clone();
-                                p.insert(key.clone(), datum.clone());
-                                p
-                            })
-                            .collect::<Vec<_>>()
-                    })
-                    .collect()
-            } else {
-                vec![HashMap::new()]
+        for (name, output) in &self.outputs {
+            if output.kind == OutputKind::File && output.path.is_none() {
+                return Err(ErrorKind::MissingOutputPath(self.name.clone(), name.clone()).into());
             }
+        }
+
+        let platforms = match self.bin {
+            Bin::Path(_) => vec![],
+            Bin::Platforms(ref paths) => paths.keys().collect(),
         };
+        let patterns = self.stop_on
+            .iter()
+            .map(|rule| &rule.pattern)
+            .chain(platforms)
+            .chain(self.fields.values().filter_map(|field| field.pattern.as_ref()))
+            .chain(self.outputs.values().filter_map(|output| output.pattern.as_ref()))
+            .chain(self.outputs
+                .values()
+                .filter_map(|output| output.block.as_ref())
+                .flat_map(|block| Some(&block.begin).into_iter().chain(block.end.as_ref())));
+        for pattern in patterns {
+            if let Err(e) = Regex::new(pattern) {
+                return Err(ErrorKind::InvalidPattern(self.name.clone(),
+                                                     pattern.clone(),
+                                                     e.to_string())
+                    .into());
+            }
+        }
 
-        let res = prod(param_sets);
-        let rl = res.len();
-        Ok(res.into_iter()
-            .cycle()
-            .enumerate()
-            .map(|(i, mut params)| {
-                params.insert(format!("repetition-{}", self.run), FieldData::UInt(i / rl));
-                params
+        for output in self.outputs.values() {
+            let pattern = match output.pattern {
+                Some(ref pattern) => Regex::new(pattern).unwrap(),
+                None => continue,
+            };
+            let names = pattern.capture_names().filter_map(|name| name).collect::<Vec<_>>();
+            let missing = output.captures.keys().find(|name| !names.contains(&name.as_str()));
+            if let Some(missing) = missing {
+                return Err(ErrorKind::InvalidPattern(self.name.clone(),
+                                                     pattern.as_str().to_string(),
+                                                     format!("it has no group named {}", missing))
+                    .into());
+            }
+        }
+
+        let bin = match self.bin {
+            Bin::Path(ref path) => Some(path),
+            Bin::Platforms(_) => None,
+        };
+        let expected = bin.into_iter().zip(&self.sha256).chain(&self.checksums);
+        for (path, digest) in expected {
+            let actual = file_sha256(Path::new(path))?;
+            if !actual.eq_ignore_ascii_case(digest) {
+                return Err(ErrorKind::ChecksumMismatch(path.clone(), digest.clone(), actual)
+                    .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects warnings about the program spec on its own.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut unreferenced = self.fields
+            .iter()
+            .filter(|&(name, field)| {
+                field.option.is_none() && !self.format.references().contains(&name.as_str())
             })
-            .take(self.repetitions.unwrap_or(1) * rl)
-            .collect())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        unreferenced.sort();
+        unreferenced.into_iter()
+            .map(|field| {
+                Warning::UnreferencedField {
+                    program: self.name.clone(),
+                    field: field,
+                }
+            })
+            .collect()
+    }
+
+    /// Estimates the runtime in seconds of one instance of this program, if the spec declares
+    /// one.
+    pub fn estimate(&self, params: &Params, threads: usize) -> Result<Option<f64>> {
+        let secs = match self.runtime {
+            None => return Ok(None),
+            Some(Runtime::Constant(secs)) => secs,
+            Some(Runtime::Formula(ref formula)) => {
+                let lookup = |name: &str| if name == THREADS_NAME {
+                    Some(threads as f64)
+                } else {
+                    params.get(name).and_then(|datum| datum.as_float())
+                };
+                Expr::parse(formula)?.eval(&lookup)?
+            }
+        };
+        // plans are ordered by their critical paths, which must compare, so e.g. 0/0 is an error
+        if !secs.is_finite() {
+            return Err(ErrorKind::InvalidEstimate(self.name.clone(), params.to_map(), secs).into());
+        }
+        Ok(Some(secs))
+    }
+
+    /// Renders the command line for a POSIX shell, see `cmd_in`.
+    #[cfg(test)]
+    pub fn cmd(&self, params: &Params) -> Result<String> {
+        self.cmd_in(params, Shell::Posix, &mut ResponseFiles::new(default_response_dir()))
+    }
+
+    /// Renders the command line for one instance in a single pass over the format template,
+    /// quoting values and the binary for `shell` and adding the values it spills to `files`.
+    ///
+    /// Optioned fields are inserted at the `<options>` marker if the format has one, and appended
+    /// to the end of the command otherwise. Placeholders without a value (e.g. `<threads>`) are
+    /// left in place to be filled later.
+    pub fn cmd_in(&self,
+                  params: &Params,
+                  shell: Shell,
+                  files: &mut ResponseFiles)
+                  -> Result<String> {
+        let mut positional = HashMap::new();
+        let mut options = vec![];
+        for (field, datum) in params.iter() {
+            if let Some(details) = self.fields.get(field) {
+                if datum == &FieldData::Future || !details.matches(&datum) ||
+                   !details.active(params)? {
+                    continue;
+                }
+
+                let filled = details.fill_in(datum, shell, files)?;
+                if details.option.is_none() {
+                    positional.insert(field.as_str(), filled);
+                } else if !filled.is_empty() {
+                    options.push((details.order, field, filled));
+                }
+            }
+        }
+        let mut inactive = HashSet::new();
+        for (name, details) in &self.fields {
+            if details.option.is_none() && !details.active(params)? {
+                inactive.insert(name.as_str());
+            }
+        }
+        let format = self.format.without(|name| inactive.contains(name));
+        // params are unordered, so ties are broken by name to keep commands the same between runs
+        options.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        let options = options.into_iter()
+            .map(|(_, _, filled)| filled)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut cmd = match self.bin {
+            Bin::Path(ref path) => format!("{} ", shell.quote(path, true)),
+            Bin::Platforms(_) => format!("{} ", BIN_PLACEHOLDER),
+        };
+        let hash = if self.format.references().contains(&PARAMS_NAME) {
+            Some(job_hash(&self.name, params))
+        } else {
+            None
+        };
+        cmd.push_str(&format.render(|name| if name == OPTIONS_ANCHOR {
+            Some(options.clone())
+        } else if name == PARAMS_NAME && !positional.contains_key(name) {
+            hash.clone()
+        } else {
+            positional.get(name).cloned()
+        }));
+
+        let anchored = self.format
+            .segments()
+            .iter()
+            .any(|seg| seg == &Segment::Placeholder(OPTIONS_ANCHOR.to_string()));
+        if !anchored && !options.is_empty() {
+            cmd.push_str(" ");
+            cmd.push_str(&options);
+        }
+        Ok(cmd)
+    }
+
+    pub fn validate_parameters(&self, params: &HashMap<String, FieldSetting>) -> Result<()> {
+        if let Some(Runtime::Formula(ref formula)) = self.runtime {
+            Expr::parse(formula)?;
+        }
+
+        // every field must either be filled or be optional (as indicated by the option: foo field
+        // on the field object)
+        for (field, details) in &self.fields {
+            if !params.contains_key(field) && details.option.is_none() && field != THREADS_NAME &&
+               details.active_with(params, true)? {
+                return Err(ErrorKind::MissingParameter(field.clone(), self.name.clone()).into());
+            }
+
+            if !params.contains_key(field) {
+                continue;
+            }
+
+            if !details.active_with(params, false)? {
+                return Err(ErrorKind::InactiveField(field.clone(),
+                                                    self.name.clone(),
+                                                    details.condition()?)
+                    .into());
+            }
+
+            let ref param = params[field];
+            if !details.dtype.matches_setting(param) {
+                let shown = match details.dtype {
+                    FieldType::Secret => {
+                        FieldSetting::Value(details.dtype.redact(&FieldData::Future))
+                    }
+                    _ => param.clone(),
+                };
+                return Err(ErrorKind::InvalidParameterSetting(field.clone(),
+                                                              shown,
+                                                              details.dtype.clone())
+                    .into());
+            }
+            details.check(field, &param.vectorize(field)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks statically that a dependent job's parameters, along with everything its upstream
+    /// jobs pass down, fill every required field with data of the right type.
+    fn check_wiring(&self, job: &str, sources: &HashMap<String, Source>) -> Result<()> {
+        let mut fields = self.fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|&(name, _)| name);
+        for (name, details) in fields {
+            match sources.get(name) {
+                // conditional fields are checked as each instance is built
+                None if details.option.is_none() && details.when.is_empty() &&
+                        name != THREADS_NAME => {
+                    return Err(ErrorKind::UnwiredField(job.to_string(), name.clone()).into());
+                }
+                None => {}
+                Some(&Source::Matrix) => {}
+                Some(&Source::Output(ref upstream)) |
+                Some(&Source::File(ref upstream)) => {
+                    let matches = match sources[name] {
+                        Source::File(_) => {
+                            details.dtype == FieldType::Path || details.dtype == FieldType::Str
+                        }
+                        _ => details.dtype.matches(&FieldData::Future),
+                    };
+                    if !matches {
+                        return Err(ErrorKind::MiswiredField(job.to_string(),
+                                                            name.clone(),
+                                                            upstream.clone(),
+                                                            details.dtype.clone())
+                            .into());
+                    }
+                }
+                Some(&Source::Parameter(ref setting)) => {
+                    if !details.dtype.matches_setting(setting) {
+                        return Err(ErrorKind::InvalidParameterSetting(name.clone(),
+                                                                      setting.clone(),
+                                                                      details.dtype.clone())
+                            .into());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Converts text from a job matrix to the type of the field it fills.
+    fn coerce(&self, field: &str, raw: &str) -> Result<FieldData> {
+        let dtype = match self.fields.get(field) {
+            Some(details) => details.dtype.clone(),
+            None => return Ok(values::parse_value(raw)),
+        };
+        let datum = match dtype {
+            FieldType::Str | FieldType::Path => Some(FieldData::Str(raw.to_string())),
+            FieldType::Secret if is_secret_ref(raw) => Some(FieldData::Str(raw.to_string())),
+            FieldType::Secret => None,
+            FieldType::UInt => raw.parse().ok().map(FieldData::UInt),
+            FieldType::Float => raw.parse().ok().map(FieldData::Float),
+            FieldType::Bool => raw.parse().ok().map(FieldData::Bool),
+            FieldType::Duration | FieldType::Datetime | FieldType::Size | FieldType::List(_) => {
+                Some(values::parse_value(raw)).and_then(|datum| if dtype.matches(&datum) {
+                    Some(datum)
+                } else {
+                    None
+                })
+            }
+        };
+        datum.ok_or_else(|| {
+            ErrorKind::InvalidParameterData(field.to_string(),
+                                            dtype.redact(&FieldData::Str(raw.to_string())),
+                                            dtype)
+                .into()
+        })
+    }
+
+    pub fn validate_parameter_data(&self, params: &Params) -> Result<()> {
+        // every field must either be filled or be optional (as indicated by the option: foo field
+        // on the field object)
+        for (field, details) in &self.fields {
+            let active = details.active(params)?;
+            if !params.contains_key(field) && details.option.is_none() && field != THREADS_NAME &&
+               active {
+                return Err(ErrorKind::MissingParameter(field.clone(), self.name.clone()).into());
+            }
+
+            if !params.contains_key(field) {
+                continue;
+            }
+
+            if !active {
+                return Err(ErrorKind::InactiveField(field.clone(),
+                                                    self.name.clone(),
+                                                    details.condition()?)
+                    .into());
+            }
+
+            let ref param = params[field];
+            if !details.dtype.matches(param) {
+                return Err(ErrorKind::InvalidParameterData(field.clone(),
+                                                           details.dtype.redact(param),
+                                                           details.dtype.clone())
+                    .into());
+            }
+            details.check(field, &[param.clone()])?;
+        }
+
+        Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct Experiment {
-    jobs: Vec<Job>,
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FieldData {
+    Future,
+    Str(String),
+    UInt(usize),
+    Float(f64),
+    Bool(bool),
+    List(Vec<FieldData>),
+    /// A value with a readable name, written `{value: data/huge-v3.bin, label: huge}`. The value
+    /// fills the command, and the label stands in for it in instance labels and output paths.
+    Labeled { value: Box<FieldData>, label: String },
 }
 
-impl Experiment {
-    /// Converts a sequence of Job specs into a sequence of job instances ready to be sent to the
-    /// broker.
-    pub fn plan(&self,
-                threads: usize,
-                programs: &HashMap<String, Program>)
-                -> Result<Vec<JobInstance>> {
-        let mut id = 0;
-        let mut jobify = |prog: &Program, params, deps| {
-            let mut inst = JobInstance {
-                id: Some(id),
-                command: try!(prog.cmd(&params)),
-                params: params,
-                log: None,
-                threads: threads,
-                depends: deps,
-            };
+impl FieldData {
+    /// Separates a labeled datum into its value and label.
+    pub fn split_label(self) -> (FieldData, Option<String>) {
+        match self {
+            FieldData::Labeled { value, label } => (*value, Some(label)),
+            datum => (datum, None),
+        }
+    }
+
+    /// The numeric value of the datum, if it has one. Bools count as 0 or 1.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            &FieldData::Float(f) => Some(f),
+            &FieldData::UInt(u) => Some(u as f64),
+            &FieldData::Bool(b) => Some(if b { 1.0 } else { 0.0 }),
+            &FieldData::Labeled { ref value, .. } => value.as_float(),
+            _ => None,
+        }
+    }
+}
+
+impl Deserialize for FieldData {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+        where D: Deserializer
+    {
+        deserializer.deserialize(FieldDataVisitor)
+    }
+}
+
+/// Reads data by the type it was written with. Derived untagged, integers would be read as floats
+/// or floats truncated to integers, depending on which variant came first.
+struct FieldDataVisitor;
+
+impl Visitor for FieldDataVisitor {
+    type Value = FieldData;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string, number, bool, list or labeled value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::Future)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::Bool(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::UInt(v as usize))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> ::std::result::Result<FieldData, E> {
+        Ok(if v < 0 {
+            FieldData::Float(v as f64)
+        } else {
+            FieldData::UInt(v as usize)
+        })
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::Str(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> ::std::result::Result<FieldData, E> {
+        Ok(FieldData::Str(v))
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> ::std::result::Result<FieldData, V::Error>
+        where V: SeqVisitor
+    {
+        let mut items = vec![];
+        while let Some(item) = visitor.visit()? {
+            items.push(item);
+        }
+        Ok(FieldData::List(items))
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> ::std::result::Result<FieldData, V::Error>
+        where V: MapVisitor
+    {
+        let (mut value, mut label) = (None, None);
+        while let Some(key) = visitor.visit_key::<String>()? {
+            match key.as_str() {
+                "value" => value = Some(visitor.visit_value()?),
+                "label" => label = Some(visitor.visit_value()?),
+                _ => {
+                    visitor.visit_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        match (value, label) {
+            (Some(value), Some(label)) => {
+                Ok(FieldData::Labeled {
+                    value: Box::new(value),
+                    label: label,
+                })
+            }
+            (None, _) => Err(de::Error::missing_field("value")),
+            (_, None) => Err(de::Error::missing_field("label")),
+        }
+    }
+}
+
+impl ToString for FieldData {
+    fn to_string(&self) -> String {
+        match self {
+            &FieldData::Str(ref s) => s.clone(),
+            &FieldData::UInt(v) => v.to_string(),
+            &FieldData::Float(v) => v.to_string(),
+            &FieldData::Bool(v) => v.to_string(),
+            &FieldData::Future => "".to_string(),
+            &FieldData::List(ref items) => {
+                items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(",")
+            }
+            &FieldData::Labeled { ref value, .. } => value.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum FieldSetting {
+    Range {
+        from: FieldData,
+        to: FieldData,
+        step: FieldData,
+    },
+    /// The values in a file, one per line, or a column of a CSV file. Replaced by the values
+    /// themselves in `Experiment::read_value_files`.
+    File {
+        file: String,
+        column: Option<String>,
+    },
+    List(Vec<FieldData>),
+    Value(FieldData),
+}
+
+impl FieldSetting {
+    /// Every datum written in the setting, including range endpoints and steps.
+    pub fn data(&self) -> Vec<&FieldData> {
+        match self {
+            &FieldSetting::Range { ref from, ref to, ref step } => vec![from, to, step],
+            &FieldSetting::List(ref v) => v.iter().collect(),
+            &FieldSetting::File { .. } => vec![],
+            &FieldSetting::Value(ref v) => vec![v],
+        }
+    }
+
+    fn data_mut(&mut self) -> Vec<&mut FieldData> {
+        match self {
+            &mut FieldSetting::Range { ref mut from, ref mut to, ref mut step } => {
+                vec![from, to, step]
+            }
+            &mut FieldSetting::List(ref mut v) => v.iter_mut().collect(),
+            &mut FieldSetting::File { .. } => vec![],
+            &mut FieldSetting::Value(ref mut v) => vec![v],
+        }
+    }
+
+    /// The values the setting of parameter `name` takes.
+    pub fn vectorize(&self, name: &str) -> Result<Vec<FieldData>> {
+        match self {
+            &FieldSetting::Range { ref from, ref to, ref step } => {
+                let invalid_step = || ErrorKind::InvalidStep(name.to_string(), self.clone()).into();
+                let mut range = Vec::new();
+                match (from, to, step) {
+                    (&FieldData::UInt(start), &FieldData::UInt(end), &FieldData::UInt(step)) => {
+                        if step == 0 {
+                            return Err(invalid_step());
+                        }
+                        let mut cur = start;
+
+                        while cur <= end {
+                            range.push(FieldData::UInt(cur));
+                            cur = match cur.checked_add(step) {
+                                Some(next) => next,
+                                None => break,
+                            };
+                        }
+                    }
+                    _ => {
+                        // any float endpoint makes the whole range a float range
+                        let (mut cur, end, step) =
+                            match (from.as_float(), to.as_float(), step.as_float()) {
+                                (Some(from), Some(to), Some(step)) => (from, to, step),
+                                _ => {
+                                    return Err(ErrorKind::InvalidRange(name.to_string(),
+                                                                       self.clone())
+                                        .into())
+                                }
+                            };
+                        // also catches a NaN step
+                        if !(step > 0.0) {
+                            return Err(invalid_step());
+                        }
+
+                        while cur <= end {
+                            range.push(FieldData::Float(cur));
+                            cur += step;
+                        }
+                    }
+                }
+                Ok(range)
+            }
+            &FieldSetting::List(ref v) => Ok(v.clone()),
+            &FieldSetting::File { .. } => Ok(vec![]),
+            &FieldSetting::Value(ref v) => Ok(vec![v.clone()]),
+        }
+    }
+}
+
+/// Settings of the instances of a job. The `defaults` of an experiment apply to every job, and
+/// each job may override them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobSettings {
+    /// Threads per instance, possibly computed from its parameters. Defaults to the `threads`
+    /// parameter if the job sets one, and otherwise to 1.
+    pub threads: Option<Amount>,
+    /// Upper bound on the computed thread count.
+    pub max_threads: Option<usize>,
+    /// Memory per instance in GB, overriding the program's.
+    pub memory: Option<Amount>,
+    /// How many times a failed instance is retried.
+    pub retries: Option<usize>,
+    /// Seconds an instance may run before it is killed.
+    pub timeout: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Features the machine running an instance must have, such as `avx512`, `a100` or a pattern
+    /// of host names, for the scheduler to match, e.g. as a SLURM `--constraint`.
+    #[serde(default)]
+    pub constraints: Vec<String>,
+    /// Files made outside of the experiment that must exist before an instance is started, e.g.
+    /// `data/<graph>.idx`. Placeholders are filled with the parameters of each instance.
+    #[serde(default)]
+    pub requires_files: Vec<Template>,
+    /// Directory the instances write their output to.
+    pub output_dir: Option<String>,
+    /// Environment variables set for the instances.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Human-readable name of each instance, e.g. `curv <graph> k=<k> rep=<repetition>`. Besides
+    /// parameters, it may refer to `<program>`, `<id>`, `<threads>`, `<repetition>` and
+    /// `<params>`, and use the helper functions of templates such as `<stem(graph)>`.
+    pub label: Option<Template>,
+    /// Hooks of each instance, replacing those of the program one by one.
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Shell the commands are written for. Defaults to a POSIX shell.
+    pub shell: Option<Shell>,
+    /// Directory that values too long for the command line are written to, for fields with a
+    /// `response_file`. Defaults to `args` next to the plan file, or to the system's temp dir.
+    pub response_dir: Option<String>,
+    /// Content-addressed store the instances keep their output in, in place of `output_dir`.
+    /// Each instance writes to `<store>/<hash>`, file outputs included, so identical jobs share
+    /// outputs and different ones never collide.
+    pub store: Option<String>,
+}
+
+impl JobSettings {
+    /// Layers `over` on top of these settings. Its values win, except that tags are combined and
+    /// environments and hooks merged.
+    pub fn merge(&self, over: &JobSettings) -> JobSettings {
+        let mut tags = self.tags.clone();
+        tags.extend(over.tags.iter().filter(|tag| !self.tags.contains(tag)).cloned());
+        let mut constraints = self.constraints.clone();
+        constraints.extend(over.constraints
+            .iter()
+            .filter(|constraint| !self.constraints.contains(constraint))
+            .cloned());
+        let mut env = self.env.clone();
+        env.extend(over.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let mut requires_files = self.requires_files.clone();
+        requires_files.extend(over.requires_files.iter().cloned());
+        JobSettings {
+            threads: over.threads.clone().or_else(|| self.threads.clone()),
+            max_threads: over.max_threads.or(self.max_threads),
+            memory: over.memory.clone().or_else(|| self.memory.clone()),
+            retries: over.retries.or(self.retries),
+            timeout: over.timeout.or(self.timeout),
+            tags: tags,
+            constraints: constraints,
+            requires_files: requires_files,
+            output_dir: over.output_dir.clone().or_else(|| self.output_dir.clone()),
+            env: env,
+            label: over.label.clone().or_else(|| self.label.clone()),
+            hooks: self.hooks.merge(&over.hooks),
+            shell: over.shell.or(self.shell),
+            response_dir: over.response_dir.clone().or_else(|| self.response_dir.clone()),
+            store: over.store.clone().or_else(|| self.store.clone()),
+        }
+    }
+}
+
+/// A table of parameter values, one job per row, e.g. a design generated by an external tool.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Matrix {
+    /// A CSV file, or TSV if it ends in `.tsv`, with a header row naming the columns.
+    file: String,
+    /// The field filled by each column that is not named after its field.
+    #[serde(default)]
+    columns: HashMap<String, String>,
+    /// The fields filled by the table, filled in by `Experiment::read_value_files`.
+    #[serde(skip_deserializing)]
+    fields: Vec<String>,
+    /// The rows of the table. Serialized so that cache keys change with the file.
+    #[serde(skip_deserializing)]
+    rows: Vec<Vec<String>>,
+}
+
+impl Matrix {
+    /// The values of row `i`, as strings to be converted once the program is known.
+    fn row(&self, i: usize) -> Vec<(String, FieldData)> {
+        self.fields
+            .iter()
+            .zip(&self.rows[i])
+            .map(|(field, value)| (field.clone(), FieldData::Str(value.clone())))
+            .collect()
+    }
+
+    fn read(&mut self, dir: &Path) -> Result<()> {
+        let (header, rows) = values::read_table(&dir.join(&self.file))?;
+        self.fields = header.into_iter()
+            .map(|column| self.columns.get(&column).cloned().unwrap_or(column))
+            .collect();
+        self.rows = rows;
+        Ok(())
+    }
+}
+
+/// A seed generated for each repetition of a job.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Seeds {
+    /// The field the seed is passed in.
+    field: String,
+    /// Jobs in the same group get the same seed for the same repetition, so that competing
+    /// programs see the same random instances. Defaults to the job's program.
+    group: Option<String>,
+}
+
+/// Which combinations of a job's parameters make up its instances.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Design {
+    /// Every combination.
+    Grid,
+    /// One at a time: the baseline, plus each value of each parameter with every other parameter
+    /// held at its baseline.
+    Oat,
+}
+
+impl Default for Design {
+    fn default() -> Self {
+        Design::Grid
+    }
+}
+
+/// How the combinations of a job's parameters are picked.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Search {
+    /// `budget` combinations drawn uniformly without replacement, the same ones for the same
+    /// `seed` and spec.
+    Random {
+        budget: usize,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+/// Where a job gets the value of a parameter from.
+#[derive(Debug, Clone)]
+enum Source {
+    /// Set in the experiment, by the job itself or by one of its upstream jobs.
+    Parameter(FieldSetting),
+    /// An output of the named upstream program, only known once it has run.
+    Output(String),
+    /// A file output of the named upstream program, whose path is known.
+    File(String),
+    /// A column of the job's matrix, whose type is checked as each row is read.
+    Matrix,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    run: String,
+    #[serde(default)]
+    parameters: HashMap<String, FieldSetting>,
+    /// Blocks of `shared_parameters` to take parameters from, in order. The job's own parameters
+    /// override them.
+    #[serde(default, rename = "use")]
+    uses: Vec<String>,
+    repetitions: Option<usize>,
+    on_each: Option<Vec<String>>,
+    #[serde(default)]
+    priority: i64,
+    threads: Option<Amount>,
+    max_threads: Option<usize>,
+    memory: Option<Amount>,
+    retries: Option<usize>,
+    timeout: Option<f64>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    constraints: Vec<String>,
+    #[serde(default)]
+    requires_files: Vec<Template>,
+    output_dir: Option<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    label: Option<Template>,
+    #[serde(default)]
+    hooks: Hooks,
+    /// Rows of parameter values, each of which is combined with `parameters`.
+    matrix: Option<Matrix>,
+    #[serde(default)]
+    design: Design,
+    /// Values held fixed while another parameter varies in a one-at-a-time design. Parameters
+    /// without one are held at their first value.
+    #[serde(default)]
+    baseline: HashMap<String, FieldData>,
+    /// Partial combinations to leave out: every point whose values agree with one of them on all
+    /// of its parameters is removed.
+    #[serde(default)]
+    exclude: Vec<HashMap<String, FieldData>>,
+    /// Extra combinations added after the exclusions. Parameters they leave out are held at the
+    /// same values as in a one-at-a-time design.
+    #[serde(default)]
+    include: Vec<HashMap<String, FieldData>>,
+    seeds: Option<Seeds>,
+    /// The field the repetition number, counting from 0, is passed in.
+    repetition_field: Option<String>,
+}
+
+impl Job {
+    /// The settings of this job's instances: its own, falling back to the experiment's defaults.
+    pub fn settings(&self, defaults: &JobSettings) -> JobSettings {
+        defaults.merge(&JobSettings {
+            threads: self.threads.clone(),
+            max_threads: self.max_threads,
+            memory: self.memory.clone(),
+            retries: self.retries,
+            timeout: self.timeout,
+            tags: self.tags.clone(),
+            constraints: self.constraints.clone(),
+            requires_files: self.requires_files.clone(),
+            output_dir: self.output_dir.clone(),
+            env: self.env.clone(),
+            label: self.label.clone(),
+            hooks: self.hooks.clone(),
+            shell: None,
+            response_dir: None,
+            store: None,
+        })
+    }
+
+    pub fn has_depends(&self) -> bool {
+        self.on_each.is_some()
+    }
+
+    /// The parameter settings along with the generated seed, which is checked as a uint.
+    fn declared_parameters(&self) -> HashMap<String, FieldSetting> {
+        let mut params = self.parameters.clone();
+        if let Some(ref seeds) = self.seeds {
+            params.insert(seeds.field.clone(), FieldSetting::Value(FieldData::UInt(0)));
+        }
+        if let Some(ref field) = self.repetition_field {
+            params.insert(field.clone(), FieldSetting::Value(FieldData::UInt(0)));
+        }
+        params
+    }
+
+    /// Collects the parameters each instance of this job will receive, mirroring `instantiate`:
+    /// its own parameters, overridden by those passed down from upstream jobs, and the outputs of
+    /// the upstream programs.
+    fn sources(&self,
+               programs: &HashMap<String, Program>,
+               upstream: &HashMap<String, HashMap<String, Source>>)
+               -> HashMap<String, Source> {
+        let mut sources = self.declared_parameters()
+            .into_iter()
+            .map(|(k, v)| (k, Source::Parameter(v)))
+            .collect::<HashMap<_, _>>();
+        for (field, datum) in &self.baseline {
+            sources.insert(field.clone(), Source::Parameter(FieldSetting::Value(datum.clone())));
+        }
+        for field in self.matrix.iter().flat_map(|matrix| &matrix.fields) {
+            sources.insert(field.clone(), Source::Matrix);
+        }
+        for dep in self.on_each.iter().flat_map(|deps| deps) {
+            sources.extend(upstream[dep].iter().map(|(k, v)| (k.clone(), v.clone())));
+            sources.extend(programs[dep]
+                .outputs
+                .iter()
+                .map(|(k, output)| match output.kind {
+                    OutputKind::Stdout => (k.clone(), Source::Output(dep.clone())),
+                    OutputKind::File => (k.clone(), Source::File(dep.clone())),
+                }));
+        }
+        sources
+    }
+
+    /// Expands this job into instances numbered from `first_id`, given the instances already
+    /// planned for earlier jobs.
+    fn instantiate(&self,
+                   programs: &HashMap<String, Program>,
+                   planned: &HashMap<String, Vec<JobInstance>>,
+                   settings: &JobSettings,
+                   search: Option<&Search>,
+                   first_id: usize)
+                   -> Result<Vec<JobInstance>> {
+        let prog = &programs[&self.run];
+        let mut id = first_id;
+        let mut instances = vec![];
+        match self.on_each {
+            None => {
+                // no dependencies, all params are local. those from a matrix or baseline are not
+                // in the parameter settings, so they are checked as each instance is built instead.
+                let per_instance = self.matrix.is_some() || !self.baseline.is_empty() ||
+                                   !self.include.is_empty();
+                if !per_instance {
+                    prog.validate_parameters(&self.declared_parameters())?;
+                }
+                for params in self.expand(prog, search)? {
+                    let params = Params::from(params);
+                    if per_instance {
+                        prog.validate_parameter_data(&params)?;
+                    }
+                    instances.push(JobInstance::new(id,
+                                                    prog,
+                                                    params,
+                                                    vec![],
+                                                    settings,
+                                                    self.priority)?);
+                    id += 1;
+                }
+            }
+            Some(ref deps) => {
+                // expand the product of upstream instances as indices first, so that what each
+                // combination passes down is only built once it is known, rather than at every
+                // stage of the expansion.
+                let mut combos: Vec<Vec<usize>> = vec![vec![]];
+                for dep in deps {
+                    let upstream = planned[dep].len();
+                    combos = combos.into_iter()
+                        .flat_map(|combo| {
+                            (0..upstream).map(move |i| {
+                                let mut c = combo.clone();
+                                c.push(i);
+                                c
+                            })
+                        })
+                        .collect();
+                }
+
+                let futures = deps.iter()
+                    .map(|dep| {
+                        programs[dep]
+                            .outputs
+                            .iter()
+                            .filter(|&(_, output)| output.kind == OutputKind::Stdout)
+                            .map(|(k, _)| k.clone())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+
+                // the parameters from each combination are shared by the instances of every local
+                // setting that runs on it, which only keep their own parameters apart.
+                let inherited = combos.iter()
+                    .map(|combo| {
+                        let mut shared = Inherited::default();
+                        let mut pd = Vec::with_capacity(deps.len());
+                        for ((dep, &i), outputs) in deps.iter().zip(combo).zip(&futures) {
+                            let upstream = &planned[dep][i];
+                            for (k, v) in upstream.params.iter() {
+                                shared.insert(k.clone(), upstream.labeled(k, v));
+                            }
+                            for k in outputs {
+                                shared.insert(k.clone(), FieldData::Future);
+                            }
+                            for (k, path) in &upstream.output_files {
+                                shared.insert(k.clone(), FieldData::Str(path.clone()));
+                            }
+                            pd.push(upstream.id.unwrap());
+                        }
+                        (Arc::new(shared), pd)
+                    })
+                    .collect::<Vec<_>>();
+
+                for params in &self.expand(prog, search)? {
+                    for &(ref shared, ref pd) in &inherited {
+                        let p = Params::inheriting(shared.clone(), params);
+                        prog.validate_parameter_data(&p)?;
+                        instances.push(JobInstance::new(id,
+                                                        prog,
+                                                        p,
+                                                        pd.clone(),
+                                                        settings,
+                                                        self.priority)?);
+                        id += 1;
+                    }
+                }
+            }
+        }
+        Ok(instances)
+    }
+
+    /// Like `batch`, but with the values from the matrix converted to the types of their fields.
+    fn expand(&self,
+              prog: &Program,
+              search: Option<&Search>)
+              -> Result<Vec<HashMap<String, FieldData>>> {
+        let mut batch = self.batch_with(search)?;
+        for field in self.matrix.iter().flat_map(|matrix| &matrix.fields) {
+            for params in &mut batch {
+                let datum = match params.get(field) {
+                    Some(&FieldData::Str(ref raw)) => prog.coerce(field, raw)?,
+                    _ => continue,
+                };
+                params.insert(field.clone(), datum);
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Expands the parameters into one map per instance. Values from the matrix are left as
+    /// strings.
+    #[cfg(test)]
+    pub fn batch(&self) -> Result<Vec<HashMap<String, FieldData>>> {
+        self.batch_with(None)
+    }
+
+    /// Draws `budget` combinations of the parameters (and matrix rows) without enumerating the
+    /// whole grid. Parameters are taken in sorted order so that the draw only depends on `seed`.
+    fn sample_grid(&self, budget: usize, seed: u64) -> Result<Vec<HashMap<String, FieldData>>> {
+        let mut names = self.parameters.keys().collect::<Vec<_>>();
+        names.sort();
+        let dims = names.iter()
+            .map(|name| self.parameters[*name].vectorize(name))
+            .collect::<Result<Vec<_>>>()?;
+        let rows = self.matrix.as_ref().map(|matrix| matrix.rows.len()).unwrap_or(1);
+        let size = dims.iter().fold(rows, |n, values| n.saturating_mul(values.len()));
+
+        Ok(sample_indices(size, budget, &mut Rng::new(seed))
+            .into_iter()
+            .map(|mut i| {
+                let mut params = HashMap::new();
+                for (name, values) in names.iter().zip(&dims) {
+                    params.insert((*name).clone(), values[i % values.len()].clone());
+                    i /= values.len();
+                }
+                if let Some(ref matrix) = self.matrix {
+                    params.extend(matrix.row(i));
+                }
+                params
+            })
+            .collect())
+    }
+
+    fn batch_with(&self, search: Option<&Search>) -> Result<Vec<HashMap<String, FieldData>>> {
+        let res = match (self.design, search) {
+            (Design::Grid, Some(&Search::Random { budget, seed })) => {
+                self.sample_grid(budget, seed)?
+            }
+            (Design::Grid, None) => self.grid()?,
+            (Design::Oat, Some(&Search::Random { budget, seed })) => {
+                let points = self.oat()?;
+                sample_indices(points.len(), budget, &mut Rng::new(seed))
+                    .into_iter()
+                    .map(|i| points[i].clone())
+                    .collect()
+            }
+            (Design::Oat, None) => self.oat()?,
+        };
+        let mut res = res.into_iter()
+            .filter(|params| !self.exclude.iter().any(|partial| agrees(params, partial)))
+            .collect::<Vec<_>>();
+        if !self.include.is_empty() {
+            let base = self.base()?;
+            res.extend(self.with_rows(self.include
+                .iter()
+                .map(|extra| {
+                    let mut params = base.clone();
+                    params.extend(extra.clone());
+                    params
+                })
+                .collect()));
+        }
+        let rl = res.len();
+        Ok(res.into_iter()
+            .cycle()
+            .enumerate()
+            .map(|(i, mut params)| {
+                params.insert(format!("repetition-{}", self.run), FieldData::UInt(i / rl));
+                if let Some(ref field) = self.repetition_field {
+                    params.insert(field.clone(), FieldData::UInt(i / rl));
+                }
+                if let Some(ref seeds) = self.seeds {
+                    let group = seeds.group.as_ref().unwrap_or(&self.run);
+                    params.insert(seeds.field.clone(), FieldData::UInt(group_seed(group, i / rl)));
+                }
+                params
+            })
+            .take(self.repetitions.unwrap_or(1) * rl)
+            .collect())
+    }
+
+    /// The points of a one-at-a-time design, each combined with every matrix row.
+    fn oat(&self) -> Result<Vec<HashMap<String, FieldData>>> {
+        let mut names = self.parameters.keys().collect::<Vec<_>>();
+        names.sort();
+        let base = self.base()?;
+
+        let mut points = vec![base.clone()];
+        for name in names {
+            for datum in self.parameters[name].vectorize(name)? {
+                if base.get(name) != Some(&datum) {
+                    let mut point = base.clone();
+                    point.insert(name.clone(), datum);
+                    points.push(point);
+                }
+            }
+        }
+        Ok(self.with_rows(points))
+    }
+
+    /// The point that one-at-a-time designs vary from: the baseline, with every other parameter
+    /// at its first value.
+    fn base(&self) -> Result<HashMap<String, FieldData>> {
+        let mut base = self.baseline.clone();
+        for (name, setting) in &self.parameters {
+            if !base.contains_key(name) {
+                if let Some(first) = setting.vectorize(name)?.into_iter().next() {
+                    base.insert(name.clone(), first);
+                }
+            }
+        }
+        Ok(base)
+    }
+
+    /// Combines each row of the matrix, if there is one, with every parameter map. Row values win
+    /// where both set the same field.
+    fn with_rows(&self, res: Vec<HashMap<String, FieldData>>) -> Vec<HashMap<String, FieldData>> {
+        match self.matrix {
+            Some(ref matrix) => {
+                (0..matrix.rows.len())
+                    .flat_map(|i| {
+                        res.iter().map(move |params| {
+                            let mut p = params.clone();
+                            p.extend(matrix.row(i));
+                            p
+                        })
+                    })
+                    .collect()
+            }
+            None => res,
+        }
+    }
+
+    /// Every combination of the parameters (and matrix rows).
+    fn grid(&self) -> Result<Vec<HashMap<String, FieldData>>> {
+        // ordered by name, so that the same spec numbers its instances the same way every time
+        let mut param_sets = BTreeMap::new();
+
+        for (field, param) in &self.parameters {
+            param_sets.insert(field.clone(), param.vectorize(field)?);
+        }
+
+        fn prod(params: BTreeMap<String, Vec<FieldData>>) -> Vec<HashMap<String, FieldData>> {
+            let key = params.keys().next();
+            if let Some(key) = key {
+                let mut cl = params.clone();
+                cl.remove(key);
+                let subproducts = prod(cl);
+
+                subproducts.iter()
+                    .flat_map(|prod| {
+                        params[key]
+                            .iter()
+                            .map(|datum| {
+                                let mut p = prod.clone();
+                                p.insert(key.clone(), datum.clone());
+                                p
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            } else {
+                vec![HashMap::new()]
+            }
+        };
+
+        Ok(self.with_rows(prod(param_sets)))
+    }
+}
+
+/// Whether `params` has the values of `partial` for all of its parameters. Values are compared as
+/// text, since those from a matrix are still strings at this point.
+fn agrees(params: &HashMap<String, FieldData>, partial: &HashMap<String, FieldData>) -> bool {
+    partial.iter().all(|(name, value)| {
+        params.get(name).map_or(false, |datum| datum.to_string() == value.to_string())
+    })
+}
+
+/// Whether `datum` is one of the `allowed` values, compared as text like in `agrees`.
+fn allows(allowed: &[FieldData], datum: &FieldData) -> bool {
+    allowed.iter().any(|value| value.to_string() == datum.to_string())
+}
+
+/// Something suspicious, but valid, in a spec.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// The job sets a parameter that its program has no field for.
+    UnusedParameter { job: String, parameter: String },
+    /// The field has no option and its placeholder does not appear in the format, so it is never
+    /// rendered.
+    UnreferencedField { program: String, field: String },
+    /// A float fills a uint field, relying on it having no fractional part.
+    TruncatedFloat { job: String, field: String, value: f64 },
+    /// The job is repeated, but its program takes no seed, so repetitions may be identical.
+    RepetitionsWithoutSeed { job: String },
+    /// Instances of the job require a file that does not exist yet, and cannot start until
+    /// something outside the plan produces it.
+    MissingRequiredFile { job: String, path: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Warning::UnusedParameter { ref job, ref parameter } => {
+                write!(f,
+                       "job {} sets parameter {}, but the program has no such field",
+                       job,
+                       parameter)
+            }
+            &Warning::UnreferencedField { ref program, ref field } => {
+                write!(f,
+                       "field {} of {} has no option and is not referenced by its format",
+                       field,
+                       program)
+            }
+            &Warning::TruncatedFloat { ref job, ref field, value } => {
+                write!(f, "job {} fills uint field {} with float {}", job, field, value)
+            }
+            &Warning::RepetitionsWithoutSeed { ref job } => {
+                write!(f,
+                       "job {} has repetitions, but its program has no seed field",
+                       job)
+            }
+            &Warning::MissingRequiredFile { ref job, ref path } => {
+                write!(f, "job {} requires {}, which does not exist", job, path)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub spec_version: Option<u32>,
+    /// Samples each job's parameter combinations instead of running the full grid.
+    search: Option<Search>,
+    /// Values referred to as `<name>` in parameters, value files and output directories.
+    #[serde(default)]
+    vars: BTreeMap<String, FieldData>,
+    /// Named blocks of parameters that jobs take with `use: [name]`, so that jobs sweeping the
+    /// same values do not each repeat them.
+    #[serde(default)]
+    shared_parameters: BTreeMap<String, HashMap<String, FieldSetting>>,
+    #[serde(default)]
+    defaults: JobSettings,
+    jobs: Vec<Job>,
+}
+
+impl Experiment {
+    /// Names of the programs the experiment runs.
+    pub fn programs(&self) -> Vec<&str> {
+        self.jobs.iter().map(|job| job.run.as_str()).collect()
+    }
+
+    /// Collects warnings about the experiment and the programs it runs.
+    pub fn warnings(&self, programs: &HashMap<String, Program>) -> Vec<Warning> {
+        let mut warnings = vec![];
+        let mut checked: Vec<&str> = vec![];
+        for job in &self.jobs {
+            // unknown programs are reported as errors by plan
+            let prog = match programs.get(&job.run) {
+                Some(prog) => prog,
+                None => continue,
+            };
+            if !checked.contains(&job.run.as_str()) {
+                warnings.extend(prog.warnings());
+                checked.push(&job.run);
+            }
+
+            let mut params = job.parameters.keys().collect::<Vec<_>>();
+            params.sort();
+            for param in params {
+                match prog.fields.get(param) {
+                    None => {
+                        warnings.push(Warning::UnusedParameter {
+                            job: job.run.clone(),
+                            parameter: param.clone(),
+                        })
+                    }
+                    Some(field) if field.dtype == FieldType::UInt => {
+                        let float = job.parameters[param].data().into_iter().filter_map(|datum| {
+                            match datum {
+                                &FieldData::Float(f) => Some(f),
+                                _ => None,
+                            }
+                        }).next();
+                        if let Some(value) = float {
+                            warnings.push(Warning::TruncatedFloat {
+                                job: job.run.clone(),
+                                field: param.clone(),
+                                value: value,
+                            });
+                        }
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if job.repetitions.unwrap_or(1) > 1 && job.seeds.is_none() &&
+               !prog.fields.keys().any(|f| f.contains("seed")) {
+                warnings.push(Warning::RepetitionsWithoutSeed { job: job.run.clone() });
+            }
+        }
+        warnings
+    }
+
+    /// Fails on the first parameter, in job order, that its job's program has no field for,
+    /// suggesting fields with similar names. Usually a typo that would otherwise be dropped.
+    pub fn check_parameters(&self, programs: &HashMap<String, Program>) -> Result<()> {
+        for job in &self.jobs {
+            let prog = match programs.get(&job.run) {
+                Some(prog) => prog,
+                None => continue,
+            };
+
+            let mut params = job.parameters.keys().collect::<Vec<_>>();
+            params.sort();
+            for param in params {
+                if !prog.fields.contains_key(param) {
+                    let suggestions = similar_names(param, prog.fields.keys());
+                    return Err(ErrorKind::ExtraParameter(job.run.clone(),
+                                                         param.clone(),
+                                                         suggestions)
+                        .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Plans the experiment without a cache, see `plan_cached`.
+    #[cfg(test)]
+    pub fn plan(&self, programs: &HashMap<String, Program>) -> Result<Vec<JobInstance>> {
+        self.plan_cached(programs, None)
+    }
+
+    /// Converts a sequence of Job specs into a sequence of job instances ready to be sent to the
+    /// broker, reusing the stored expansion of every job whose program, definition and upstream
+    /// jobs are unchanged since it was last planned with the same `cache`.
+    ///
+    /// Instances are returned in dispatch order: highest priority first, then longest remaining
+    /// dependency chain first, so the critical path is started before embarrassingly parallel
+    /// leaves.
+    pub fn plan_cached(&self,
+                       programs: &HashMap<String, Program>,
+                       cache: Option<&PlanCache>)
+                       -> Result<Vec<JobInstance>> {
+        let mut next_id = 0;
+        let mut jobmap: HashMap<String, Vec<JobInstance>> = HashMap::new();
+        // the first id and number of instances of each planned job, and its cache key
+        let mut ranges: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut keys: HashMap<String, String> = HashMap::new();
+        // the parameters passed down to the dependents of each planned job
+        let mut sources: HashMap<String, HashMap<String, Source>> = HashMap::new();
+        // every program is checked once up front, however many jobs run it, since checking one
+        // hashes its binary and inputs
+        let mut checked = HashSet::new();
+        for job in &self.jobs {
+            match programs.get(&job.run) {
+                Some(prog) if checked.insert(&job.run) => prog.validate()?,
+                Some(_) => {}
+                None => {
+                    return Err(ErrorKind::InvalidProgram(job.run.clone(),
+                                                         programs.keys().cloned().collect())
+                        .into())
+                }
+            }
+        }
+
+        for job in &self.jobs {
+            let deps = job.on_each.as_ref().map(|deps| deps.as_slice()).unwrap_or(&[]);
+            for dep in deps {
+                if !jobmap.contains_key(dep) {
+                    return Err(ErrorKind::UnknownDependency(job.run.clone(), dep.clone()).into());
+                }
+            }
+
+            let job_sources = job.sources(programs, &sources);
+            if job.has_depends() {
+                programs[&job.run].check_wiring(&job.run, &job_sources)?;
+            }
+            sources.insert(job.run.clone(), job_sources);
+
+            let settings = job.settings(&self.defaults);
+            let upstream = deps.iter().map(|dep| ranges[dep]).collect::<Vec<_>>();
+            let key = match cache {
+                Some(_) => {
+                    let upstream_keys =
+                        deps.iter().map(|dep| keys[dep].as_str()).collect::<Vec<_>>();
+                    Some(cache_key(&programs[&job.run],
+                                   job,
+                                   &settings,
+                                   self.search.as_ref(),
+                                   &upstream_keys)?)
+                }
+                None => None,
+            };
+
+            let cached = match (cache, key.as_ref()) {
+                (Some(cache), Some(key)) => cache.load(key),
+                _ => None,
+            };
+            let instances = match cached {
+                Some(entry) => entry.rebase(next_id, &upstream),
+                None => {
+                    let instances = job.instantiate(programs,
+                                                    &jobmap,
+                                                    &settings,
+                                                    self.search.as_ref(),
+                                                    next_id)?;
+                    if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+                        cache.store(key, next_id, &upstream, &instances)?;
+                    }
+                    instances
+                }
+            };
+
+            ranges.insert(job.run.clone(), (next_id, instances.len()));
+            next_id += instances.len();
+            if let Some(key) = key {
+                keys.insert(job.run.clone(), key);
+            }
+            jobmap.insert(job.run.clone(), instances);
+        }
+
+        let mut instances = jobmap.into_iter()
+            .flat_map(|(_, x)| x)
+            .collect::<Vec<JobInstance>>();
+        instances.sort_by_key(|inst| inst.id);
+
+        // outputs with the same path would be overwritten by whichever instance finishes last
+        {
+            let mut writers: HashMap<&str, &JobInstance> = HashMap::new();
+            for inst in &instances {
+                for path in inst.output_files.values() {
+                    if let Some(other) = writers.insert(path, inst) {
+                        return Err(ErrorKind::OutputCollision(path.clone(),
+                                                              other.describe(),
+                                                              inst.describe())
+                            .into());
+                    }
+                }
+            }
+        }
+
+        // a dependency is at least as urgent as anything waiting on it, and is worth starting
+        // before anything with a shorter chain of work behind it. dependencies are always planned
+        // before their dependents, so walking back from the highest id settles both in one pass.
+        let position = instances.iter()
+            .enumerate()
+            .map(|(i, inst)| (inst.id.unwrap(), i))
+            .collect::<HashMap<_, _>>();
+        // jobs without a runtime estimate count as one second of work
+        let cost = instances.iter().map(|inst| inst.runtime.unwrap_or(1.0)).collect::<Vec<f64>>();
+        let mut path = cost.clone();
+        for i in (0..instances.len()).rev() {
+            let priority = instances[i].priority;
+            for dep in instances[i].depends.clone() {
+                if let Some(&j) = position.get(&dep) {
+                    if instances[j].priority < priority {
+                        instances[j].priority = priority;
+                    }
+                    if path[j] < cost[j] + path[i] {
+                        path[j] = cost[j] + path[i];
+                    }
+                }
+            }
+        }
+
+        let mut order = (0..instances.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            instances[b]
+                .priority
+                .cmp(&instances[a].priority)
+                .then(path[b].partial_cmp(&path[a]).unwrap())
+                .then(instances[a].id.cmp(&instances[b].id))
+        });
+        let mut slots = instances.into_iter().map(Some).collect::<Vec<_>>();
+        let instances = order.into_iter()
+            .map(|i| slots[i].take().unwrap())
+            .collect::<Vec<_>>();
+
+        Ok(instances)
+    }
+
+    /// Replaces every setting that refers to a file of values with the values it contains, and
+    /// reads the matrix of every job that has one.
+    /// Relative paths are taken relative to `dir`, normally the directory of the experiment.
+    pub fn read_value_files(&mut self, dir: &Path) -> Result<()> {
+        for job in &mut self.jobs {
+            if let Some(ref mut matrix) = job.matrix {
+                matrix.read(dir)?;
+            }
+            for setting in job.parameters.values_mut() {
+                let values = match setting {
+                    &mut FieldSetting::File { ref file, ref column } => {
+                        values::read_values(&dir.join(file), column.as_ref().map(|c| c.as_str()))?
+                    }
+                    _ => continue,
+                };
+                *setting = FieldSetting::List(values);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the shared parameters each job uses into its own, without overriding those it sets
+    /// itself. Later blocks in `use` override earlier ones.
+    pub fn apply_shared_parameters(&mut self) -> Result<()> {
+        for job in &mut self.jobs {
+            let mut params = HashMap::new();
+            for name in &job.uses {
+                match self.shared_parameters.get(name) {
+                    Some(block) => params.extend(block.clone()),
+                    None => {
+                        return Err(ErrorKind::UnknownSharedParameters(job.run.clone(),
+                                                                      name.clone())
+                            .into())
+                    }
+                }
+            }
+            params.extend(job.parameters.drain());
+            job.parameters = params;
+        }
+        Ok(())
+    }
+
+    /// Sets the directory response files are written to, unless the spec sets one.
+    pub fn default_response_dir(&mut self, dir: &str) {
+        if self.defaults.response_dir.is_none() {
+            self.defaults.response_dir = Some(dir.to_string());
+        }
+    }
+
+    /// Sends the output of every instance to the store in `dir`.
+    pub fn set_store(&mut self, dir: &str) {
+        self.defaults.store = Some(dir.to_string());
+    }
+
+    /// Overrides the value of a declared variable.
+    pub fn set_var(&mut self, name: &str, value: FieldData) -> Result<()> {
+        match self.vars.get_mut(name) {
+            Some(var) => {
+                *var = value;
+                Ok(())
+            }
+            None => Err(ErrorKind::UnknownVar(name.to_string()).into()),
+        }
+    }
+
+    /// Replaces references to variables with their values. Values containing references are read
+    /// again once they are filled in, so that `<max_k>` in a uint field becomes a uint.
+    pub fn apply_vars(&mut self) {
+        let vars = self.vars
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        let substitute = |text: &mut String| {
+            let rendered = Template::parse(text).render(|name| vars.get(name).cloned());
+            let changed = rendered != *text;
+            *text = rendered;
+            changed
+        };
+
+        if let Some(ref mut dir) = self.defaults.output_dir {
+            substitute(dir);
+        }
+        for job in &mut self.jobs {
+            for setting in job.parameters.values_mut() {
+                if let &mut FieldSetting::File { ref mut file, .. } = setting {
+                    substitute(file);
+                }
+                for datum in setting.data_mut() {
+                    let text = match datum {
+                        &mut FieldData::Str(ref mut text) => {
+                            if !substitute(text) {
+                                continue;
+                            }
+                            text.clone()
+                        }
+                        _ => continue,
+                    };
+                    *datum = values::parse_value(&text);
+                }
+            }
+            if let Some(ref mut matrix) = job.matrix {
+                substitute(&mut matrix.file);
+            }
+            if let Some(ref mut dir) = job.output_dir {
+                substitute(dir);
+            }
+        }
+    }
+
+    /// Overrides a parameter of every job running `run`, or of every job that sets it if `run`
+    /// is not given.
+    pub fn set_parameter(&mut self,
+                         run: Option<&str>,
+                         parameter: &str,
+                         setting: FieldSetting)
+                         -> Result<()> {
+        let mut found = false;
+        for job in self.jobs.iter_mut().filter(|job| match run {
+            Some(run) => job.run == run,
+            None => job.parameters.contains_key(parameter),
+        }) {
+            job.parameters.insert(parameter.to_string(), setting.clone());
+            found = true;
+        }
+
+        match (found, run) {
+            (true, _) => Ok(()),
+            (false, Some(run)) => Err(ErrorKind::UnknownJob(run.to_string()).into()),
+            (false, None) => Err(ErrorKind::UnknownParameter(parameter.to_string()).into()),
+        }
+    }
+
+    /// Overrides the priority of every job running `run`.
+    pub fn set_priority(&mut self, run: &str, priority: i64) -> Result<()> {
+        let mut found = false;
+        for job in self.jobs.iter_mut().filter(|job| job.run == run) {
+            job.priority = priority;
+            found = true;
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(ErrorKind::UnknownJob(run.to_string()).into())
+        }
+    }
+}
+
+/// Parameters that several instances have in common, such as those the instances of a job get from
+/// one combination of its dependencies.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inherited {
+    values: HashMap<String, FieldData>,
+    /// The labels of the labeled values, which are kept apart as in `JobInstance::value_labels`.
+    labels: BTreeMap<String, String>,
+}
+
+impl Inherited {
+    fn insert(&mut self, name: String, datum: FieldData) {
+        match datum.split_label() {
+            (datum, Some(label)) => {
+                self.labels.insert(name.clone(), label);
+                self.values.insert(name, datum);
+            }
+            (datum, None) => {
+                self.labels.remove(&name);
+                self.values.insert(name, datum);
+            }
+        }
+    }
+}
+
+/// The parameters of one instance. Those it inherits are shared with the other instances that
+/// inherit them, so that only its own are stored per instance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    shared: Arc<Inherited>,
+    own: HashMap<String, FieldData>,
+}
+
+impl Params {
+    /// `own` on top of `shared`, except that the inherited values win where both set a parameter.
+    fn inheriting(shared: Arc<Inherited>, own: &HashMap<String, FieldData>) -> Params {
+        let own = own.iter()
+            .filter(|&(name, _)| !shared.values.contains_key(name))
+            .map(|(name, datum)| (name.clone(), datum.clone()))
+            .collect();
+        Params {
+            shared: shared,
+            own: own,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FieldData> {
+        self.own.get(name).or_else(|| self.shared.values.get(name))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.own.contains_key(name) || self.shared.values.contains_key(name)
+    }
+
+    pub fn insert(&mut self, name: String, datum: FieldData) {
+        self.own.insert(name, datum);
+    }
+
+    pub fn iter<'a>(&'a self) -> Box<Iterator<Item = (&'a String, &'a FieldData)> + 'a> {
+        let own = &self.own;
+        Box::new(own.iter().chain(self.shared.values.iter().filter(move |&(name, _)| {
+            !own.contains_key(name)
+        })))
+    }
+
+    /// Copies the parameters into a single map.
+    pub fn to_map(&self) -> HashMap<String, FieldData> {
+        self.iter().map(|(name, datum)| (name.clone(), datum.clone())).collect()
+    }
+}
+
+impl From<HashMap<String, FieldData>> for Params {
+    fn from(own: HashMap<String, FieldData>) -> Params {
+        Params {
+            shared: Arc::default(),
+            own: own,
+        }
+    }
+}
+
+impl<'a> Index<&'a str> for Params {
+    type Output = FieldData;
+
+    fn index(&self, name: &str) -> &FieldData {
+        self.get(name).expect("no such parameter")
+    }
+}
+
+impl Serialize for Params {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl Deserialize for Params {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+        where D: Deserializer
+    {
+        HashMap::deserialize(deserializer).map(Params::from)
+    }
+}
+
+/// What the tools running a plan need to know about a program, written once ahead of the
+/// instances of the program rather than with each of them. Instances refer to it by name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramHeader {
+    pub name: String,
+    /// The program's outputs, for the tools that run plans to read from stdout.
+    #[serde(default)]
+    pub outputs: BTreeMap<String, Output>,
+    #[serde(default)]
+    pub stdout: StdoutFormat,
+    #[serde(default)]
+    pub stop_on: Vec<StopRule>,
+    /// The program's `exit_codes`, as pairs sorted by status, since plan files in JSON can only
+    /// have string keys.
+    #[serde(default)]
+    pub exit_codes: Vec<(i32, Outcome)>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// The program's `max_concurrent`, which limits the instances of the program running at once.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// The binaries of a program with one per platform, one of which replaces the `<bin>` that
+    /// the commands of its instances start with.
+    #[serde(default)]
+    pub bins: BTreeMap<String, String>,
+}
+
+/// The headers of the programs run by a plan, by name.
+pub fn program_headers(plan: &[JobInstance],
+                       programs: &HashMap<String, Program>)
+                       -> Vec<ProgramHeader> {
+    plan.iter()
+        .map(|inst| inst.program.as_str())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|name| programs.get(name).map(Program::header))
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JobInstance {
+    pub id: Option<usize>,
+    /// Stable identifier of the program and parameters this instance runs, see `job_hash`. Empty
+    /// in plans written before instances were hashed.
+    #[serde(default)]
+    pub hash: String,
+    pub program: String,
+    command: String,
+    params: Params,
+    log: Option<String>,
+    pub depends: Vec<usize>,
+    pub threads: usize,
+    priority: i64,
+    /// Estimated runtime in seconds, if the program declares one.
+    pub runtime: Option<f64>,
+    /// Memory in GB, if the program declares it.
+    pub memory: Option<f64>,
+    #[serde(default)]
+    pub retries: Option<usize>,
+    #[serde(default)]
+    pub timeout: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Machine features the instance needs, see `JobSettings::constraints`.
+    #[serde(default)]
+    pub constraints: Vec<String>,
+    /// Files that must exist before the instance is started, see `JobSettings::requires_files`.
+    #[serde(default)]
+    pub requires_files: Vec<String>,
+    /// Paths of the program's file outputs, by output.
+    #[serde(default)]
+    pub output_files: BTreeMap<String, String>,
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Human-readable name, for display in place of the id.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The labels of the labeled values among the parameters, by parameter.
+    #[serde(default)]
+    pub value_labels: BTreeMap<String, String>,
+    /// The hooks the experiment sets for the instance's job, each replacing the same hook of the
+    /// program's header.
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Absolute path of the program's binary, if it was resolved at plan time.
+    #[serde(default)]
+    pub bin: Option<String>,
+    /// SHA-256 of the program's binary, if it was resolved or its checksum was verified at plan
+    /// time, so that results can be traced to the build that produced them.
+    #[serde(default)]
+    pub bin_sha256: Option<String>,
+    /// The values spilled from the command, which are not part of the plan but written next to
+    /// it.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub response_files: ResponseFiles,
+}
+
+/// The number of single-character insertions, deletions and substitutions turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..b.len() + 1).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            let best = min(min(prev[j + 1] + 1, cur[j] + 1), prev[j] + cost);
+            cur.push(best);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// The names among `candidates` close enough to `name` to be what was meant, closest first.
+fn similar_names<'a, I>(name: &str, candidates: I) -> Vec<String>
+    where I: Iterator<Item = &'a String>
+{
+    let limit = max(name.chars().count() / 3, 1);
+    let mut similar = candidates.map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .collect::<Vec<_>>();
+    similar.sort();
+    similar.into_iter().map(|(_, candidate)| candidate.clone()).collect()
+}
+
+/// Hashes a program name and parameter set into a hex SHA-256 digest that is stable across runs
+/// and machines, unlike ids, which depend on planning order.
+pub fn job_hash(program: &str, params: &Params) -> String {
+    let sorted = params.iter().collect::<BTreeMap<_, _>>();
+    let mut hasher = Sha256::new();
+    hasher.input_str(program);
+    hasher.input_str("\0");
+    hasher.input_str(&serde_json::to_string(&sorted).unwrap());
+    hasher.result_str()
+}
+
+/// Hashes the contents of a file into a hex SHA-256 digest.
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 65536];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+    }
+    Ok(hasher.result_str())
+}
+
+/// Narrows a plan to the jobs tagged with any of `tags` (or every job, if `tags` is empty),
+/// along with the jobs they depend on. Jobs tagged with any of `exclude` are then dropped, and so
+/// is everything depending on them, since it could not run.
+pub fn select_tags(plan: Vec<JobInstance>,
+                   tags: &[String],
+                   exclude: &[String])
+                   -> Vec<JobInstance> {
+    let keep = {
+        let by_id = plan.iter()
+            .filter_map(|inst| inst.id.map(|id| (id, inst)))
+            .collect::<HashMap<_, _>>();
+
+        let mut keep = HashSet::new();
+        let mut stack = plan.iter()
+            .filter(|inst| tags.is_empty() || inst.tags.iter().any(|tag| tags.contains(tag)))
+            .filter_map(|inst| inst.id)
+            .collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            if keep.insert(id) {
+                if let Some(inst) = by_id.get(&id) {
+                    stack.extend(inst.depends.iter().cloned());
+                }
+            }
+        }
+
+        loop {
+            let dropped = keep.iter()
+                .cloned()
+                .filter(|id| {
+                    let inst = by_id[id];
+                    inst.tags.iter().any(|tag| exclude.contains(tag)) ||
+                    inst.depends.iter().any(|dep| !keep.contains(dep))
+                })
+                .collect::<Vec<_>>();
+            if dropped.is_empty() {
+                break;
+            }
+            for id in dropped {
+                keep.remove(&id);
+            }
+        }
+        keep
+    };
+
+    plan.into_iter().filter(|inst| inst.id.map_or(false, |id| keep.contains(&id))).collect()
+}
+
+/// Warns about each file required by instances of the plan that does not exist, as told by
+/// `exists`, once per job.
+pub fn missing_required_files<F>(plan: &[JobInstance], exists: F) -> Vec<Warning>
+    where F: Fn(&str) -> bool
+{
+    plan.iter()
+        .flat_map(|inst| inst.requires_files.iter().map(move |path| (&inst.program, path)))
+        .filter(|&(_, path)| !exists(path))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|(job, path)| {
+            Warning::MissingRequiredFile {
+                job: job.clone(),
+                path: path.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Narrows a plan to the jobs that are out of date, as make would see them: those with a file
+/// output that is missing or older than a file named by one of their path parameters, and the jobs
+/// depending on them. Jobs without file outputs are always kept, having nothing to tell by, and
+/// kept jobs stop waiting on the dependencies that were dropped, whose outputs are already there.
+/// `modified` gives the time a file was last modified, or `None` if it does not exist. A change to
+/// the specs that leaves the output paths as they were goes unnoticed.
+pub fn select_stale<F>(plan: Vec<JobInstance>,
+                       programs: &HashMap<String, Program>,
+                       modified: F)
+                       -> Vec<JobInstance>
+    where F: Fn(&str) -> Option<SystemTime>
+{
+    let stale = {
+        let mut ids = plan.iter().filter_map(|inst| inst.id).collect::<Vec<_>>();
+        ids.sort();
+        let by_id = plan.iter()
+            .filter_map(|inst| inst.id.map(|id| (id, inst)))
+            .collect::<HashMap<_, _>>();
+
+        // dependencies are always planned, and so numbered, before their dependents
+        let mut stale = HashSet::new();
+        for id in ids {
+            let inst = by_id[&id];
+            if inst.depends.iter().any(|dep| stale.contains(dep)) ||
+               inst.outdated(programs.get(&inst.program), &modified) {
+                stale.insert(id);
+            }
+        }
+        stale
+    };
+
+    plan.into_iter()
+        .filter(|inst| inst.id.map_or(false, |id| stale.contains(&id)))
+        .map(|mut inst| {
+            inst.depends.retain(|dep| stale.contains(dep));
+            inst
+        })
+        .collect()
+}
+
+/// Draws a pilot subset of `n` of the jobs without dependencies, keeping every job whose
+/// dependencies are all kept. With `stratify_by`, the jobs are grouped by their value of that
+/// parameter and each group gets its proportional share, so that none is left out by chance.
+pub fn sample_plan(plan: Vec<JobInstance>,
+                   n: usize,
+                   stratify_by: Option<&str>,
+                   seed: u64)
+                   -> Vec<JobInstance> {
+    let keep = {
+        let mut ids = plan.iter().filter_map(|inst| inst.id).collect::<Vec<_>>();
+        ids.sort();
+        let by_id = plan.iter()
+            .filter_map(|inst| inst.id.map(|id| (id, inst)))
+            .collect::<HashMap<_, _>>();
+
+        let mut strata: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for &id in ids.iter().filter(|id| by_id[id].depends.is_empty()) {
+            let key = stratify_by.and_then(|param| by_id[&id].params.get(param))
+                .map(|datum| datum.to_string())
+                .unwrap_or_default();
+            strata.entry(key).or_insert_with(Vec::new).push(id);
+        }
+
+        // largest remainder: each stratum gets the floor of its share, and the samples left over
+        // go to the strata that lost the most to rounding
+        let total = strata.values().map(|ids| ids.len()).sum::<usize>();
+        let n = min(n, total);
+        let mut quotas = strata.values()
+            .map(|ids| n * ids.len() / total)
+            .collect::<Vec<_>>();
+        let mut order = (0..quotas.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let size = strata.values().nth(i).unwrap().len();
+            (total - n * size % total, i)
+        });
+        let left = n - quotas.iter().sum::<usize>();
+        for &i in order.iter().take(left) {
+            quotas[i] += 1;
+        }
+
+        let mut rng = Rng::new(seed);
+        let mut keep = HashSet::new();
+        for (stratum, &quota) in strata.values().zip(&quotas) {
+            keep.extend(sample_indices(stratum.len(), quota, &mut rng)
+                .into_iter()
+                .map(|i| stratum[i]));
+        }
+
+        // dependencies are always planned, and so numbered, before their dependents
+        for id in ids {
+            let depends = &by_id[&id].depends;
+            if !depends.is_empty() && depends.iter().all(|dep| keep.contains(dep)) {
+                keep.insert(id);
+            }
+        }
+        keep
+    };
+
+    plan.into_iter().filter(|inst| inst.id.map_or(false, |id| keep.contains(&id))).collect()
+}
+
+/// Name of the file in an output store that records what each of its directories holds.
+pub const STORE_MANIFEST: &'static str = "manifest.json";
+
+/// What ran to produce one directory of an output store.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoreEntry {
+    pub program: String,
+    pub params: BTreeMap<String, FieldData>,
+}
+
+/// Records the program and parameters of each instance of a plan using the output store in
+/// `dir` in the store's manifest, keeping the entries of earlier plans.
+pub fn write_store_manifest(plan: &[JobInstance], dir: &Path) -> Result<()> {
+    let path = dir.join(STORE_MANIFEST);
+    let mut manifest: BTreeMap<String, StoreEntry> = if path.exists() {
+        serde_json::from_reader(File::open(&path)?)?
+    } else {
+        BTreeMap::new()
+    };
+
+    for inst in plan {
+        manifest.entry(inst.hash.clone()).or_insert_with(|| {
+            StoreEntry {
+                program: inst.program.clone(),
+                params: inst.params.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            }
+        });
+    }
+
+    fs::create_dir_all(dir)?;
+    serde_json::to_writer_pretty(&mut File::create(&path)?, &manifest)?;
+    Ok(())
+}
+
+impl JobInstance {
+    pub fn new(id: usize,
+               prog: &Program,
+               params: Params,
+               depends: Vec<usize>,
+               settings: &JobSettings,
+               priority: i64)
+               -> Result<JobInstance> {
+        let Params { shared, own } = params;
+        let mut value_labels = shared.labels.clone();
+        let own = own.into_iter()
+            .map(|(name, datum)| {
+                let (datum, label) = datum.split_label();
+                if let Some(label) = label {
+                    value_labels.insert(name.clone(), label);
+                }
+                (name, datum)
+            })
+            .collect();
+        let mut params = Params {
+            shared: shared,
+            own: own,
+        };
+        let threads = match settings.threads {
+            Some(ref amount) => Some(amount.eval(&params, None)?),
+            None => params.get(THREADS_NAME).and_then(|datum| datum.as_float()),
+        };
+        let threads = threads.map_or(1, |threads| max(threads.ceil() as usize, 1));
+        let threads = settings.max_threads.map_or(threads, |cap| min(threads, cap));
+        // a threads field always gets the thread count the instance is scheduled with
+        if prog.fields.contains_key(THREADS_NAME) {
+            params.insert(THREADS_NAME.to_string(), FieldData::UInt(threads));
+        }
+        let memory = match settings.memory {
+            Some(ref amount) => Some(amount.eval(&params, Some(threads))?),
+            None => prog.memory,
+        };
+        let hash = job_hash(&prog.name, &params);
+        // with a store, the instance keeps everything it writes in its own directory of it
+        let stored = settings.store.as_ref().map(|store| Path::new(store).join(&hash));
+        let (requires_files, output_files) = {
+            let lookup = |name: &str| match params.get(name) {
+                Some(&FieldData::Future) | None => None,
+                Some(datum) => Some(datum.to_string()),
+            };
+            let output_files = prog.outputs
+                .iter()
+                .filter_map(|(name, output)| match (output.kind, output.path.as_ref()) {
+                    (OutputKind::File, Some(path)) => {
+                        let path = path.render(&lookup);
+                        Some((name.clone(),
+                              match stored {
+                                  Some(ref dir) => dir.join(path).to_string_lossy().into_owned(),
+                                  None => path,
+                              }))
+                    }
+                    _ => None,
+                })
+                .collect();
+            (settings.requires_files.iter().map(|path| path.render(&lookup)).collect(),
+             output_files)
+        };
+        let label = settings.label.as_ref().map(|label| {
+            let repetition = format!("repetition-{}", prog.name);
+            label.render(|name| match name {
+                "id" => Some(id.to_string()),
+                "program" => Some(prog.name.clone()),
+                THREADS_NAME => Some(threads.to_string()),
+                "repetition" => params.get(&repetition).map(|datum| datum.to_string()),
+                PARAMS_NAME => Some(hash.clone()),
+                _ if value_labels.contains_key(name) => value_labels.get(name).cloned(),
+                _ => {
+                    params.get(name)
+                        .and_then(|datum| if datum == &FieldData::Future {
+                            None
+                        } else {
+                            Some(datum.to_string())
+                        })
+                }
+            })
+        });
+        let mut response_files = ResponseFiles::new(settings.response_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(default_response_dir));
+        let mut inst = JobInstance {
+            id: Some(id),
+            hash: hash,
+            program: prog.name.clone(),
+            command: prog.cmd_in(&params,
+                                 settings.shell.unwrap_or_default(),
+                                 &mut response_files)?,
+            runtime: prog.estimate(&params, threads)?,
+            memory: memory,
+            params: params,
+            log: None,
+            threads: threads,
+            depends: depends,
+            priority: priority,
+            retries: settings.retries,
+            timeout: settings.timeout,
+            tags: settings.tags.clone(),
+            constraints: settings.constraints.clone(),
+            requires_files: requires_files,
+            output_files: output_files,
+            output_dir: match stored {
+                Some(dir) => Some(dir.to_string_lossy().into_owned()),
+                None => {
+                    settings.output_dir
+                        .as_ref()
+                        .map(|dir| Template::parse(dir).render(|name| value_labels.get(name).cloned()))
+                }
+            },
+            env: settings.env.clone(),
+            label: label,
+            value_labels: value_labels,
+            hooks: settings.hooks.clone(),
+            bin: prog.resolved_bin.clone(),
+            bin_sha256: prog.resolved_sha256
+                .clone()
+                .or_else(|| prog.sha256.as_ref().map(|digest| digest.to_lowercase())),
+            response_files: response_files,
+        };
+
+        inst.apply(THREADS_NAME, FieldData::UInt(threads))?;
+        if inst.command.len() > ARG_MAX {
+            return Err(ErrorKind::CommandTooLong(prog.name.clone(),
+                                                 inst.params.to_map(),
+                                                 inst.command.len())
+                .into());
+        }
+        Ok(inst)
+    }
+
+    /// Whether a file output of the instance is missing or older than a file named by one of its
+    /// path parameters, or it has no file outputs to tell by.
+    fn outdated<F>(&self, prog: Option<&Program>, modified: &F) -> bool
+        where F: Fn(&str) -> Option<SystemTime>
+    {
+        fn paths<'a>(dtype: &FieldType, datum: &'a FieldData) -> Vec<&'a str> {
+            match (dtype, datum) {
+                (&FieldType::Path, &FieldData::Str(ref path)) => vec![path],
+                (&FieldType::List(ref inner), &FieldData::List(ref data)) => {
+                    data.iter().flat_map(|datum| paths(inner, datum)).collect()
+                }
+                _ => vec![],
+            }
+        }
+
+        let oldest = self.output_files
+            .values()
+            .map(|path| modified(path))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|times| times.into_iter().min());
+        let oldest = match oldest {
+            Some(time) => time,
+            None => return true,
+        };
+        let fields = match prog {
+            Some(prog) => &prog.fields,
+            None => return true,
+        };
+        self.params
+            .iter()
+            .filter_map(|(name, datum)| fields.get(name).map(|field| paths(&field.dtype, datum)))
+            .flat_map(|paths| paths)
+            // a missing input is left for the job itself to report
+            .any(|path| modified(path).map_or(true, |time| time > oldest))
+    }
+
+    /// The program and id of the instance, and its label if it has one, for messages.
+    fn describe(&self) -> String {
+        let id = self.id.map_or(String::new(), |id| format!(" #{}", id));
+        match self.label {
+            Some(ref label) => format!("{}{} ({})", self.program, id, label),
+            None => format!("{}{}", self.program, id),
+        }
+    }
+
+    /// `datum`, the value of parameter `name`, with its label if it had one.
+    fn labeled(&self, name: &str, datum: &FieldData) -> FieldData {
+        match self.value_labels.get(name) {
+            Some(label) => {
+                FieldData::Labeled {
+                    value: Box::new(datum.clone()),
+                    label: label.clone(),
+                }
+            }
+            None => datum.clone(),
+        }
+    }
+
+    pub fn apply(&mut self, param: &str, datum: FieldData) -> Result<()> {
+        self.command = self.command.replace(&format!("<{}>", param), &datum.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_yaml;
+    use serde_json;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::Read;
+    use std::time::{Duration, UNIX_EPOCH};
+
+
+    #[test]
+    fn deser_field_simple() {
+        let field_raw = "
+            type: str
+            ";
+
+        let field: Field = serde_yaml::from_str(&field_raw).unwrap();
+
+        assert!(field.dtype == FieldType::Str);
+    }
+
+    #[test]
+    fn deser_field_full() {
+        let field_raw = "type: float\naka: ['δ']\noption: '--delta <delta>'\nbatch: {join: ','}";
+        let field: Field = serde_yaml::from_str(&field_raw).unwrap();
+
+        assert!(field ==
+                Field {
+            dtype: FieldType::Float,
+            aka: vec!["δ".to_string()],
+            option: Some(Template::parse("--delta <delta>")),
+            batch: BatchType::Join(",".to_string()),
+            response_file: None,
+            render: None,
+            separator: None,
+            pattern: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+            order: 0,
+            when: BTreeMap::new(),
+        });
+    }
+
+    #[test]
+    fn deser_output_simple() {
+        let out_raw = "msg: \"approximation ratio\"";
+        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+
+        assert!(out ==
+                Output {
+            msg: "approximation ratio".to_string(),
+            aka: vec![],
+            kind: OutputKind::Stdout,
+            path: None,
+            pattern: None,
+            captures: BTreeMap::new(),
+            tail: None,
+            block: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+        });
+    }
+
+    #[test]
+    fn deser_output_full() {
+        let out_raw = "msg: approximation ratio\naka: ['ratio']";
+        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+
+        assert!(out ==
+                Output {
+            msg: "approximation ratio".to_string(),
+            aka: vec!["ratio".to_string()],
+            kind: OutputKind::Stdout,
+            path: None,
+            pattern: None,
+            captures: BTreeMap::new(),
+            tail: None,
+            block: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+        })
+    }
+
+    #[test]
+    fn deser_output_bounds() {
+        let out_raw = "msg: runtime\nmin: 0\nmin_exclusive: true";
+        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+
+        assert!(out.min == Some(0.0) && out.min_exclusive);
+        assert!(out.max.is_none() && !out.max_exclusive);
+    }
+
+    #[test]
+    fn deser_output_tail() {
+        let out_raw = "msg: ratio\ntail: 100\nblock: {begin: '^== summary'}";
+        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+
+        assert!(out.tail == Some(100) && out.min.is_none());
+        assert!(out.block ==
+                Some(Block {
+                    begin: "^== summary".to_string(),
+                    end: None,
+                }));
+    }
+
+    #[test]
+    fn estimate_runtime() {
+        let prog_raw = "
+            name: est
+            bin: est
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            runtime: 'k * 2 / threads'
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let params = Params::from(hashmap!{
+            "k".to_string() => FieldData::Float(10.0),
+        });
+
+        assert!(prog.estimate(&params, 4).unwrap() == Some(5.0));
+        assert!(prog.estimate(&Params::default(), 4).is_err());
+        let zero = Params::from(hashmap!{ "k".to_string() => FieldData::UInt(0) });
+        match *prog.estimate(&zero, 0).unwrap_err().kind() {
+            ErrorKind::InvalidEstimate(..) => {}
+            ref kind => panic!("expected an invalid estimate, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn cmd_options_anchor() {
+        let prog_raw = "
+            name: sub
+            bin: sub
+            format: 'run <options> <graph>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              bias:
+                type: float
+                option: '--bias <bias>'
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let params = hashmap!{
+            "graph".to_string() => FieldData::Str("a.bin".to_string()),
+            "bias".to_string() => FieldData::Float(0.5),
+        };
+
+        assert!(prog.cmd(&Params::from(params)).unwrap() == "sub run --bias 0.5 a.bin");
+    }
+
+    #[test]
+    fn cmd_option_order() {
+        let prog_raw = "
+            name: sub
+            bin: sub
+            format: '<options> <graph>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              bias:
+                type: float
+                option: '--bias <bias>'
+              mode:
+                type: str
+                option: '<mode>'
+                order: -1
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let params = Params::from(hashmap!{
+            "graph".to_string() => FieldData::Str("a.bin".to_string()),
+            "bias".to_string() => FieldData::Float(0.5),
+            "mode".to_string() => FieldData::Str("fast".to_string()),
+        });
+
+        assert!(prog.cmd(&params).unwrap() == "sub fast --bias 0.5 a.bin");
+    }
+
+    #[test]
+    fn cmd_option_order_stable() {
+        let prog_raw = "
+            name: sub
+            bin: sub
+            format: '<options>'
+            outputs: {}
+            fields:
+              alpha:
+                type: uint
+                option: '-a <alpha>'
+              beta:
+                type: uint
+                option: '-b <beta>'
+              gamma:
+                type: uint
+                option: '-c <gamma>'
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let params = Params::from(hashmap!{
+            "gamma".to_string() => FieldData::UInt(3),
+            "alpha".to_string() => FieldData::UInt(1),
+            "beta".to_string() => FieldData::UInt(2),
+        });
+
+        assert!(prog.cmd(&params).unwrap() == "sub -a 1 -b 2 -c 3");
+    }
+
+    #[test]
+    fn cmd_helpers() {
+        let prog_raw = "
+            name: sub
+            bin: sub
+            format: '<graph> --out <stem(graph)>-<round(bias, 1)>-<hash(params)>.out'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              bias:
+                type: float
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let params = Params::from(hashmap!{
+            "graph".to_string() => FieldData::Str("data/a.bin".to_string()),
+            "bias".to_string() => FieldData::Float(0.26),
+        });
+        let cmd = prog.cmd(&params).unwrap();
+
+        assert!(prog.warnings().is_empty());
+        assert!(cmd.starts_with("sub data/a.bin --out a-0.3-") && cmd.ends_with(".out"));
+        assert!(cmd.len() == "sub data/a.bin --out a-0.3-12345678.out".len());
+        assert!(cmd == prog.cmd(&params).unwrap());
+    }
+
+    #[test]
+    fn cmd_options_appended() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let params = hashmap!{
+            "graph".to_string() => FieldData::Str("a.bin".to_string()),
+            "k".to_string() => FieldData::Float(10.0),
+            "epsilon".to_string() => FieldData::Float(0.1),
+            "delta".to_string() => FieldData::Float(0.01),
+            "skip_linear".to_string() => FieldData::Bool(false),
+            "relgap".to_string() => FieldData::Float(0.05),
+        };
+
+        assert!(prog.cmd(&Params::from(params)).unwrap() ==
+                "target/release/interdict a.bin 10 0.1 0.01 --threads <threads> --log <log> \
+                 --rel-gap 0.05");
+    }
+
+    #[test]
+    fn job_hash_stable() {
+        let a = Params::from(hashmap!{
+            "k".to_string() => FieldData::Float(10.0),
+            "graph".to_string() => FieldData::Str("a.bin".to_string()),
+        });
+        let mut b = Params::default();
+        b.insert("graph".to_string(), FieldData::Str("a.bin".to_string()));
+        b.insert("k".to_string(), FieldData::Float(10.0));
+        // inherited parameters count the same as the instance's own, and win over them
+        let mut shared = Inherited::default();
+        shared.insert("graph".to_string(), FieldData::Str("a.bin".to_string()));
+        let c = Params::inheriting(Arc::new(shared),
+                                   &hashmap!{
+                                       "k".to_string() => FieldData::Float(10.0),
+                                       "graph".to_string() => FieldData::Str("b.bin".to_string()),
+                                   });
+
+        assert!(job_hash("curv", &a) == job_hash("curv", &b));
+        assert!(job_hash("curv", &a) == job_hash("curv", &c));
+        assert!(job_hash("curv", &a) != job_hash("interdict", &a));
+        assert!(job_hash("curv", &a).len() == 64);
+    }
+
+    #[test]
+    fn warnings_curv() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        assert!(exp.warnings(&hashmap!{ "curv".to_string() => prog }).is_empty());
+    }
+
+    #[test]
+    fn warnings_suspicious() {
+        let prog_raw = "
+            name: sus
+            bin: sus
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+              graph:
+                type: path
+            ";
+        let exp_raw = "
+            jobs:
+              - run: sus
+                parameters:
+                  k: [1, 2.0]
+                  graph: a.bin
+                  kk: 3
+                repetitions: 2
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        let warnings = exp.warnings(&hashmap!{ "sus".to_string() => prog });
+        assert!(warnings ==
+                vec![Warning::UnreferencedField {
+                         program: "sus".to_string(),
+                         field: "graph".to_string(),
+                     },
+                     Warning::TruncatedFloat {
+                         job: "sus".to_string(),
+                         field: "k".to_string(),
+                         value: 2.0,
+                     },
+                     Warning::UnusedParameter {
+                         job: "sus".to_string(),
+                         parameter: "kk".to_string(),
+                     },
+                     Warning::RepetitionsWithoutSeed { job: "sus".to_string() }]);
+    }
+
+    #[test]
+    fn check_parameters_suggests() {
+        let prog_raw = "
+            name: sub
+            bin: sub
+            format: '<epsilon> <graph>'
+            outputs: {}
+            fields:
+              epsilon:
+                type: float
+              graph:
+                type: path
+            ";
+        let exp_raw = "
+            jobs:
+              - run: sub
+                parameters:
+                  epsilonn: 0.1
+                  graph: a.bin
+            ";
+        let progs = hashmap!{ "sub".to_string() => serde_yaml::from_str(&prog_raw).unwrap() };
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        match *exp.check_parameters(&progs).unwrap_err().kind() {
+            ErrorKind::ExtraParameter(ref job, ref param, ref suggestions) => {
+                assert!(job == "sub" && param == "epsilonn");
+                assert!(suggestions == &vec!["epsilon".to_string()]);
+            }
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+        assert!(similar_names("grap", ["graph".to_string(), "k".to_string()].iter()) ==
+                vec!["graph".to_string()]);
+    }
+
+    #[test]
+    fn deser_problem_curv() {
+        let _prob: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn deser_problem_interdict() {
+        let _prob: Program =
+            serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn deser_problem_interdict_validate() {
+        let _prob: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+    }
+
+    #[test]
+    fn deser_exp_curv() {
+        let _prob: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn deser_exp_interdict() {
+        let _prob: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn fill_no_option() {
+        let field = Field {
+            dtype: FieldType::UInt,
+            aka: vec![],
+            batch: BatchType::None,
+            option: None,
+            response_file: None,
+            render: None,
+            separator: None,
+            pattern: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+            order: 0,
+            when: BTreeMap::new(),
+        };
+
+        assert!(field.fill_with(&FieldData::UInt(27)).unwrap() == "27".to_string());
+    }
+
+    #[test]
+    fn fill_option_bool() {
+        let field = Field {
+            dtype: FieldType::Bool,
+            aka: vec![],
+            batch: BatchType::None,
+            option: Some(Template::parse("--flag")),
+            response_file: None,
+            render: None,
+            separator: None,
+            pattern: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+            order: 0,
+            when: BTreeMap::new(),
+        };
+
+        assert!(field.fill_with(&FieldData::Bool(true)).unwrap() == "--flag".to_string());
+        assert!(field.fill_with(&FieldData::Bool(false)).unwrap() == "".to_string());
+    }
+
+    #[test]
+    fn fill_option_value() {
+        let field = Field {
+            dtype: FieldType::Float,
+            aka: vec![],
+            batch: BatchType::None,
+            option: Some(Template::parse("--float <foo>")),
+            response_file: None,
+            render: None,
+            separator: None,
+            pattern: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+            order: 0,
+            when: BTreeMap::new(),
+        };
+
+        println!("{}", field.fill_with(&FieldData::Float(0.27)).unwrap());
+        assert!(field.fill_with(&FieldData::Float(0.27)).unwrap() == "--float 0.27".to_string());
+    }
+
+    #[test]
+    fn fill_duration() {
+        let field: Field = serde_yaml::from_str("{type: duration, option: '--limit <limit>'}")
+            .unwrap();
+        assert!(field.fill_with(&FieldData::Str("2h30m".to_string())).unwrap() == "--limit 9000");
+        assert!(field.fill_with(&FieldData::UInt(90)).unwrap() == "--limit 90");
+        assert!(field.fill_with(&FieldData::Str("soon".to_string())).is_err());
+
+        let field: Field = serde_yaml::from_str("{type: duration, render: hms}").unwrap();
+        assert!(field.fill_with(&FieldData::Str("5m".to_string())).unwrap() == "00:05:00");
+    }
+
+    #[test]
+    fn fill_size() {
+        let field: Field = serde_yaml::from_str("{type: size, option: '--buffer <buffer>'}")
+            .unwrap();
+        assert!(field.fill_with(&FieldData::Str("512M".to_string())).unwrap() ==
+                "--buffer 536870912");
+        assert!(field.fill_with(&FieldData::UInt(4096)).unwrap() == "--buffer 4096");
+
+        let field: Field = serde_yaml::from_str("{type: size, render: mib}").unwrap();
+        assert!(field.fill_with(&FieldData::Str("2G".to_string())).unwrap() == "2048");
+        let field: Field = serde_yaml::from_str("{type: size, render: original}").unwrap();
+        assert!(field.fill_with(&FieldData::Str("2G".to_string())).unwrap() == "2G");
+        assert!(field.fill_with(&FieldData::Str("big".to_string())).is_err());
+    }
+
+    #[test]
+    fn fill_list() {
+        let files = FieldData::List(vec![FieldData::Str("a b.bin".to_string()),
+                                         FieldData::Str("c.bin".to_string())]);
+        let field: Field = serde_yaml::from_str("{type: {list: path}, option: '--file <file>'}")
+            .unwrap();
+        let mut spilled = ResponseFiles::new(default_response_dir());
+        assert!(field.fill_in(&files, Shell::Cmd, &mut spilled).unwrap() ==
+                "--file \"a b.bin\" --file c.bin");
+
+        let field: Field = serde_yaml::from_str("{type: {list: uint}, separator: ','}").unwrap();
+        let ks = FieldData::List(vec![FieldData::UInt(1), FieldData::UInt(2)]);
+        assert!(field.fill_with(&ks).unwrap() == "1,2");
+        assert!(field.fill_with(&files).is_err());
+        assert!(field.fill_with(&FieldData::UInt(1)).is_err());
+    }
+
+    #[test]
+    fn fill_secret() {
+        let field: Field = serde_yaml::from_str("{type: secret, option: '--token <token>'}")
+            .unwrap();
+        assert!(field.fill_with(&FieldData::Str("env:API_TOKEN".to_string())).unwrap() ==
+                "--token <secret:env:API_TOKEN>");
+        let keyring = FieldData::Str("keyring:svc/me".to_string());
+        let mut spilled = ResponseFiles::new(default_response_dir());
+        assert!(field.fill_in(&keyring, Shell::Cmd, &mut spilled).unwrap() ==
+                "--token <secret:keyring:svc/me>");
+        // a secret pasted into the spec is refused rather than planned
+        assert!(field.fill_with(&FieldData::Str("hunter2".to_string())).is_err());
+        assert!(field.fill_with(&FieldData::Str("env:1X".to_string())).is_err());
+    }
+
+    #[test]
+    fn fill_datetime() {
+        let field: Field = serde_yaml::from_str("{type: datetime, render: '%Y/%m/%d'}").unwrap();
+        let since = FieldData::Str("2017-03-01T00:00:00Z".to_string());
+        assert!(field.fill_with(&since).unwrap() == "2017/03/01");
+        assert!(field.fill_with(&FieldData::Str("March".to_string())).is_err());
+        assert!(field.fill_with(&FieldData::UInt(2017)).is_err());
+    }
+
+    #[test]
+    fn fill_response_file() {
+        let field = Field {
+            dtype: FieldType::Str,
+            aka: vec![],
+            batch: BatchType::None,
+            option: Some(Template::parse("--files <files>")),
+            response_file: Some(Template::parse("@<file>")),
+            render: None,
+            separator: None,
+            pattern: None,
+            min: None,
+            max: None,
+            min_exclusive: false,
+            max_exclusive: false,
+            order: 0,
+            when: BTreeMap::new(),
+        };
+
+        assert!(field.fill_with(&FieldData::Str("a,b".to_string())).unwrap() == "--files a,b");
+
+        let dir = env::temp_dir().join("waluigi-response-file-test");
+        let mut spilled = ResponseFiles::new(&dir);
+        let long = vec!["data/graph.bin"; 1000].join(",");
+        let filled = field.fill_in(&FieldData::Str(long.clone()), Shell::Posix, &mut spilled)
+            .unwrap();
+        assert!(filled.starts_with(&format!("--files @{}/", dir.display())));
+
+        // nothing is written until the plan is
+        let path = &filled["--files @".len()..];
+        assert!(!Path::new(path).exists());
+        spilled.write().unwrap();
+        let mut contents = String::new();
+        File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+        assert!(contents == long);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let field = Field { response_file: None, ..field };
+        let huge = vec!["data/graph.bin"; 10000].join(",");
+        match *field.fill_with(&FieldData::Str(huge)).unwrap_err().kind() {
+            ErrorKind::ArgumentTooLong(..) => {}
+            ref kind => panic!("expected a too long argument, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn job_validate_curv() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        for job in &exp.jobs {
+            prog.validate_parameters(&job.parameters).unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn job_invalid_curv() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+
+        for job in &exp.jobs {
+            prog.validate_parameters(&job.parameters).unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_pattern() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<problem>'
+            outputs: {}
+            fields:
+              problem:
+                type: str
+                pattern: '[a-z]+-v\\d'
+            ")
+            .unwrap();
+        let setting = |values: &str| {
+            hashmap!{ "problem".to_string() => values::parse_setting(values) }
+        };
+
+        assert!(prog.validate_parameters(&setting("cover-v1,pack-v2")).is_ok());
+        let err = prog.validate_parameters(&setting("cover-v1,pack-v2x")).unwrap_err();
+        assert!(err.kind().code() == "pattern_mismatch");
+        assert!(err.to_string().contains("pack-v2x"));
+    }
+
+    #[test]
+    fn validate_bounds() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<epsilon> <k>'
+            outputs: {}
+            fields:
+              epsilon:
+                type: float
+                min: 0
+                min_exclusive: true
+                max: 1
+              k:
+                type: uint
+                max: 10
+            ")
+            .unwrap();
+        let settings = |epsilon: &str, k: &str| {
+            hashmap!{
+                "epsilon".to_string() => values::parse_setting(epsilon),
+                "k".to_string() => values::parse_setting(k),
+            }
+        };
+
+        assert!(prog.validate_parameters(&settings("0.1,1", "{from: 1, to: 10, step: 1}")).is_ok());
+        assert!(prog.validate_parameters(&settings("0,0.5", "1")).is_err());
+        assert!(prog.validate_parameters(&settings("0.5", "{from: 2, to: 12, step: 2}")).is_err());
+        let err = prog.validate_parameters(&settings("1.5", "1")).unwrap_err();
+        assert!(err.kind().code() == "out_of_range");
+    }
+
+    #[test]
+    fn validate_conditional() {
+        let prog: Program = serde_yaml::from_str("
+            name: opt
+            bin: opt
+            format: '<method> <steps>'
+            outputs: {}
+            fields:
+              method:
+                type: str
+              alpha:
+                type: float
+                option: '--alpha <alpha>'
+                when: {method: annealing}
+              steps:
+                type: uint
+                when: {method: [annealing, tempering]}
+            ")
+            .unwrap();
+        let settings = |method: &str, extra: &[(&str, &str)]| {
+            let mut params = hashmap!{ "method".to_string() => values::parse_setting(method) };
+            for &(name, value) in extra {
+                params.insert(name.to_string(), values::parse_setting(value));
+            }
+            params
+        };
+
+        assert!(prog.validate_parameters(&settings("greedy", &[])).is_ok());
+        assert!(prog.validate_parameters(&settings("annealing", &[("steps", "10")])).is_ok());
+        let err = prog.validate_parameters(&settings("tempering", &[])).unwrap_err();
+        assert!(err.kind().code() == "missing_parameter");
+        let err = prog.validate_parameters(&settings("greedy,annealing",
+                                                     &[("alpha", "0.5"), ("steps", "10")]))
+            .unwrap_err();
+        assert!(err.kind().code() == "inactive_field");
+
+        let params = |method: &str| {
+            Params::from(hashmap!{
+                "method".to_string() => FieldData::Str(method.to_string()),
+                "alpha".to_string() => FieldData::Float(0.5),
+                "steps".to_string() => FieldData::UInt(10),
+            })
+        };
+        assert!(prog.validate_parameter_data(&params("annealing")).is_ok());
+        assert!(prog.validate_parameter_data(&params("tempering")).is_err());
+        assert!(prog.cmd(&params("annealing")).unwrap() == "opt annealing 10 --alpha 0.5");
+        assert!(prog.cmd(&params("greedy")).unwrap() == "opt greedy");
+    }
+
+    #[test]
+    fn job_batch_curv() {
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        for job in &exp.jobs {
+            let batch = job.batch().unwrap();
+            assert!(batch.len() == 2310)
+        }
+    }
+
+    #[test]
+    fn job_batch_interdict() {
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+
+        for (job, &size) in exp.jobs.iter().zip(&vec![330, 1]) {
+            let batch = job.batch().unwrap();
+            println!("{} {}", batch.len(), size);
+            assert!(batch.len() == size)
+        }
+    }
+
+    #[test]
+    fn plan_curv() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        let map = hashmap!{
+            "curv".to_string() => prog,
+        };
+
+        assert!(exp.plan(&map).unwrap().len() == 2310);
+    }
+
+    #[test]
+    fn plan_passes_stop_rules() {
+        let mut prog: Program =
+            serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap()).unwrap();
+        prog.stop_on = serde_yaml::from_str("[{pattern: 'diverged at \\d+', outcome: diverged}]")
+            .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        let map = hashmap!{ "curv".to_string() => prog };
+        let header = program_headers(&exp.plan(&map).unwrap(), &map).remove(0);
+        assert!(header.stop_on[0].outcome == "diverged" && !header.stop_on[0].failure);
+
+        let mut prog: Program =
+            serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap()).unwrap();
+        prog.stop_on = serde_yaml::from_str("[{pattern: 'diverged (', outcome: diverged}]")
+            .unwrap();
+        assert!(exp.plan(&hashmap!{ "curv".to_string() => prog }).is_err());
+    }
+
+    #[test]
+    fn plan_verifies_checksums() {
+        let dir = ::std::env::temp_dir().join("waluigi-checksum-test");
+        fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("est");
+        File::create(&bin).unwrap().write_all(b"hello\n").unwrap();
+        let prog = |digest: &str| -> Program {
+            serde_yaml::from_str(&format!("
+                name: est
+                bin: {}
+                sha256: {}
+                format: '<k>'
+                outputs: {{}}
+                fields:
+                  k:
+                    type: uint
+                ",
+                                          bin.display(),
+                                          digest))
+                .unwrap()
+        };
+        let exp: Experiment = serde_yaml::from_str("
+            jobs:
+              - run: est
+                parameters:
+                  k: 1
+            ")
+            .unwrap();
+
+        let good = "5891B5B522D5DF086D0FF0B110FBD9D21BB4FC7163AF34D08286A2E846F6BE03";
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog(good) }).unwrap();
+        assert!(plan[0].bin_sha256 == Some(good.to_lowercase()));
+        let stale = "0000000000000000000000000000000000000000000000000000000000000000";
+        assert!(exp.plan(&hashmap!{ "est".to_string() => prog(stale) }).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn plan_records_resolved_checksum() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = ::std::env::temp_dir().join("waluigi-resolved-checksum-test");
+        fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("est-695");
+        File::create(&bin).unwrap().write_all(b"hello\n").unwrap();
+        fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est-695
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            ")
+            .unwrap();
+        let exp: Experiment = serde_yaml::from_str("jobs: [{run: est, parameters: {k: 1}}]")
+            .unwrap();
+
+        let mut map = hashmap!{ "est".to_string() => prog };
+        let plan = exp.plan(&map).unwrap();
+        assert!(serde_json::to_string(&plan[0]).unwrap().contains(r#""bin_sha256":null"#));
+
+        map.get_mut("est").unwrap().resolve_bin(&[dir.display().to_string()]).unwrap();
+        let plan = exp.plan(&map).unwrap();
+        let json = serde_json::to_string(&plan[0]).unwrap();
+        let digest = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+        assert!(json.contains(&format!(r#""bin_sha256":"{}""#, digest)));
+        assert!(json.contains(&format!(r#""bin":"{}""#, bin.display())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plan_quotes_for_windows() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: target/release/est
+            format: '<graph> <k>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              k:
+                type: uint
+              name:
+                type: str
+                option: '--name <name>'
+            ")
+            .unwrap();
+        let exp: Experiment = serde_yaml::from_str("
+            defaults:
+              shell: cmd
+            jobs:
+              - run: est
+                parameters:
+                  graph: data/my graph.bin
+                  k: 1
+                  name: a&b %PATH%
+            ")
+            .unwrap();
+
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog }).unwrap();
+        assert!(plan[0].command ==
+                "target\\release\\est \"data\\my graph.bin\" 1 --name \"a&b \"^%\"PATH\"^%");
+    }
+
+    #[test]
+    fn plan_platform_bins() {
+        let prog = |bins: &str| -> Program {
+            serde_yaml::from_str(&format!("
+                name: est
+                bin: {}
+                format: '<k>'
+                outputs: {{}}
+                fields:
+                  k:
+                    type: uint
+                ",
+                                          bins))
+                .unwrap()
+        };
+        let exp: Experiment = serde_yaml::from_str("
+            jobs:
+              - run: est
+                parameters:
+                  k: 1
+            ")
+            .unwrap();
+
+        let map = hashmap!{
+            "est".to_string() => prog("{linux-x86_64: bin/est, 'node\\d+': /opt/est}"),
+        };
+        let plan = exp.plan(&map).unwrap();
+        assert!(plan[0].command == "<bin> 1");
+        assert!(program_headers(&plan, &map)[0].bins["node\\d+"] == "/opt/est");
+
+        let invalid = prog("{'node(': /opt/est}");
+        assert!(exp.plan(&hashmap!{ "est".to_string() => invalid }).is_err());
+    }
+
+    #[test]
+    fn plan_passes_exit_codes() {
+        let mut prog: Program =
+            serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap()).unwrap();
+        prog.exit_codes = serde_yaml::from_str("
+            3: {outcome: infeasible}
+            137: {outcome: oom, failure: true, retry: true}
+        ")
+            .unwrap();
+        prog.max_concurrent = Some(2);
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+
+        let map = hashmap!{ "curv".to_string() => prog };
+        let header = program_headers(&exp.plan(&map).unwrap(), &map).remove(0);
+        assert!(header.exit_codes[0].0 == 3 && !header.exit_codes[0].1.failure);
+        assert!(header.exit_codes[1].0 == 137 && header.exit_codes[1].1.retry);
+        assert!(header.max_concurrent == Some(2));
+
+        // the codes survive a round trip through the plan file
+        let json = serde_json::to_string(&header).unwrap();
+        let read: ProgramHeader = serde_json::from_str(&json).unwrap();
+        assert!(read.exit_codes == header.exit_codes);
+    }
+
+    #[test]
+    fn plan_interdict() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+
+        let map = hashmap!{
+            "interdict".to_string() => prog,
+            "interdict-validate".to_string() => validate,
+        };
+
+        assert!(exp.plan(&map).unwrap().len() == 660);
+    }
+
+    #[test]
+    fn plan_priority_lifts_dependencies() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+        let mut exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+        exp.set_priority("interdict-validate", 10).unwrap();
+        assert!(exp.set_priority("curv", 10).is_err());
+
+        let map = hashmap!{
+            "interdict".to_string() => prog,
+            "interdict-validate".to_string() => validate,
+        };
+
+        let plan = exp.plan(&map).unwrap();
+        assert!(plan.iter().all(|inst| inst.priority == 10));
+    }
+
+    #[test]
+    fn plan_dependencies_first() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
+            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
+                .unwrap();
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
 
-            inst.apply("threads", FieldData::UInt(threads))?;
-            id += 1;
-            Ok(inst)
+        let map = hashmap!{
+            "interdict".to_string() => prog,
+            "interdict-validate".to_string() => validate,
         };
-        let mut jobmap: HashMap<String, Vec<JobInstance>> = HashMap::new();
-        for job in &self.jobs {
-            if !programs.contains_key(&job.run) {
-                return Err(ErrorKind::InvalidProgram(job.run.clone(),
-                                                     programs.keys().cloned().collect())
-                    .into());
-            }
 
-            if !job.has_depends() {
-                programs[&job.run].validate_parameters(&job.parameters)?;
-                jobmap.insert(job.run.clone(),
-                              job.batch()?
-                                  .into_iter()
-                                  .map(|params| jobify(&programs[&job.run], params, vec![]))
-                                  .collect::<Result<_>>()?); // no dependencies, all params are local
-            } else if let Some(ref deps) = job.on_each {
-                let mut batch =
-                    job.batch()?.into_iter().map(|params| (params, vec![])).collect::<Vec<_>>();
-                for dep in deps {
-                    if !jobmap.contains_key(dep) {
-                        return Err(ErrorKind::UnknownDependency(job.run.clone(), dep.clone())
-                            .into());
-                    }
+        let plan = exp.plan(&map).unwrap();
+        let mut seen = vec![];
+        for inst in &plan {
+            assert!(inst.depends.iter().all(|dep| seen.contains(&Some(*dep))));
+            seen.push(inst.id);
+        }
+    }
 
-                    batch = batch.into_iter()
-                        .flat_map(|(params, par_deps)| {
-                            jobmap[dep]
-                                .iter()
-                                .map(|dep_params| {
-                                    let mut p = params.clone();
-                                    let mut pd = par_deps.clone();
-                                    p.extend(dep_params.params.clone().into_iter());
-                                    p.extend(programs[dep]
-                                        .outputs
-                                        .clone()
-                                        .into_iter()
-                                        .map(|(k, _)| (k, FieldData::Future)));
-                                    pd.push(dep_params.id.unwrap());
-                                    (p, pd)
-                                })
-                                .collect::<Vec<_>>()
-                        })
-                        .collect();
-                }
+    #[test]
+    fn plan_checks_wiring() {
+        let exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+        let validate = |fields: &str| -> Program {
+            serde_yaml::from_str(&format!("
+                name: interdict-validate
+                bin: validate
+                format: '<graph> <seeds>'
+                outputs: {{}}
+                fields:
+                  graph:
+                    type: path
+                  {}
+                ",
+                                          fields))
+                .unwrap()
+        };
 
-                jobmap.insert(job.run.clone(),
-                              batch.into_iter()
-                                  .map(|(params, deps)| {
-                                      programs[&job.run]
-                                          .validate_parameter_data(&params)
-                                          .and_then(|_| jobify(&programs[&job.run], params, deps))
-                                  })
-                                  .collect::<Result<_>>()?);
-            }
+        let unwired = validate("seeds: {type: str}\n                  missing: {type: uint}");
+        let miswired = validate("seeds: {type: path}");
+        for downstream in vec![unwired, miswired] {
+            let map = hashmap!{
+                "interdict".to_string() =>
+                    serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+                        .unwrap(),
+                "interdict-validate".to_string() => downstream,
+            };
+            assert!(exp.plan(&map).is_err());
         }
-
-        Ok(jobmap.into_iter()
-            .flat_map(|(_, x)| x)
-            .collect::<Vec<JobInstance>>())
     }
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct JobInstance {
-    id: Option<usize>,
-    command: String,
-    params: HashMap<String, FieldData>,
-    log: Option<String>,
-    depends: Vec<usize>,
-    threads: usize,
-}
+    #[test]
+    fn plan_passes_captures() {
+        let prog_raw = |pattern: &str| {
+            format!("
+                name: est
+                bin: est
+                format: '<k>'
+                stdout: text
+                outputs:
+                  result:
+                    pattern: '{}'
+                    captures: {{ratio: float, rounds: uint}}
+                fields:
+                  k:
+                    type: uint
+                ",
+                    pattern)
+        };
+        let exp: Experiment = serde_yaml::from_str("jobs: [{run: est, parameters: {k: 1}}]")
+            .unwrap();
+        let programs = |pattern: &str| {
+            hashmap!{ "est".to_string() => serde_yaml::from_str(&prog_raw(pattern)).unwrap() }
+        };
 
-impl JobInstance {
-    pub fn apply(&mut self, param: &str, datum: FieldData) -> Result<()> {
-        self.command = self.command.replace(&format!("<{}>", param), &datum.to_string());
-        Ok(())
+        let map = programs("ratio (?P<ratio>[0-9.]+) after (?P<rounds>[0-9]+) rounds");
+        let header = program_headers(&exp.plan(&map).unwrap(), &map).remove(0);
+        assert!(header.stdout == StdoutFormat::Text);
+        assert!(header.outputs["result"].captures["rounds"] == FieldType::UInt);
+        let err = exp.plan(&programs("ratio (?P<ratio>[0-9.]+)")).unwrap_err();
+        assert!(err.kind().code() == "invalid_pattern");
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use serde_yaml;
-    use std::fs::File;
+    #[test]
+    fn plan_file_outputs() {
+        let gen_raw = "
+            name: gen
+            bin: gen
+            format: '<graph>'
+            outputs:
+              sketch:
+                kind: file
+                path: 'sketches/<stem(graph)>.sk'
+            fields:
+              graph:
+                type: path
+            ";
+        let use_raw = "
+            name: use
+            bin: use
+            format: '<sketch>'
+            outputs: {}
+            fields:
+              sketch:
+                type: path
+            ";
+        let exp_raw = "
+            jobs:
+              - run: gen
+                parameters: {graph: data/a.bin}
+              - run: use
+                on_each: [gen]
+            ";
+        let mut map = hashmap!{
+            "gen".to_string() => serde_yaml::from_str(gen_raw).unwrap(),
+            "use".to_string() => serde_yaml::from_str(use_raw).unwrap(),
+        };
+        let mut exp: Experiment = serde_yaml::from_str(exp_raw).unwrap();
+
+        let mut plan = exp.plan(&map).unwrap();
+        plan.sort_by_key(|inst| inst.id);
+        assert!(plan[0].output_files["sketch"] == "sketches/a.sk");
+        assert!(plan[1].command == "use sketches/a.sk");
 
+        // a store moves the file outputs, and dependents follow them there
+        exp.set_store("store");
+        let mut plan = exp.plan(&map).unwrap();
+        plan.sort_by_key(|inst| inst.id);
+        let dir = Path::new("store").join(&plan[0].hash);
+        let sketch = dir.join("sketches/a.sk").to_string_lossy().into_owned();
+        assert!(plan[0].output_dir == Some(dir.to_string_lossy().into_owned()));
+        assert!(plan[0].output_files["sketch"] == sketch);
+        assert!(plan[1].command == format!("use {}", sketch));
+
+        map.insert("gen".to_string(),
+                   serde_yaml::from_str(&gen_raw.replace("path: 'sketches/<stem(graph)>.sk'", ""))
+                       .unwrap());
+        assert!(exp.plan(&map).unwrap_err().kind().code() == "missing_output_path");
+    }
 
     #[test]
-    fn deser_field_simple() {
-        let field_raw = "
-            type: str
+    fn plan_shares_inherited_params() {
+        let gen_raw = "
+            name: gen
+            bin: gen
+            format: '<graph>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+            ";
+        let use_raw = "
+            name: use
+            bin: use
+            format: '<graph> <k>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              k:
+                type: uint
             ";
+        let exp_raw = "
+            jobs:
+              - run: gen
+                parameters: {graph: [a.bin, b.bin]}
+              - run: use
+                on_each: [gen]
+                parameters: {k: [1, 2, 3]}
+            ";
+        let map = hashmap!{
+            "gen".to_string() => serde_yaml::from_str(gen_raw).unwrap(),
+            "use".to_string() => serde_yaml::from_str(use_raw).unwrap(),
+        };
+        let exp: Experiment = serde_yaml::from_str(exp_raw).unwrap();
 
-        let field: Field = serde_yaml::from_str(&field_raw).unwrap();
+        let plan = exp.plan(&map).unwrap();
+        let uses = plan.iter().filter(|inst| inst.program == "use").collect::<Vec<_>>();
+        assert!(uses.len() == 6);
+        for a in &uses {
+            // only the job's own parameters and repetition are stored per instance
+            assert!(a.params.own.len() == 2 && a.params.own.contains_key("k"));
+            for b in &uses {
+                let same_upstream = a.depends == b.depends;
+                assert!(Arc::ptr_eq(&a.params.shared, &b.params.shared) == same_upstream);
+                assert!((a.params["graph"] == b.params["graph"]) == same_upstream);
+            }
+        }
 
-        assert!(field.dtype == FieldType::Str);
+        // inherited values win over the instance's own
+        let mut shared = Inherited::default();
+        shared.insert("k".to_string(), FieldData::UInt(1));
+        let params = Params::inheriting(Arc::new(shared),
+                                        &hashmap!{
+                                            "k".to_string() => FieldData::UInt(2),
+                                            "r".to_string() => FieldData::UInt(3),
+                                        });
+        assert!(params["k"] == FieldData::UInt(1) && params["r"] == FieldData::UInt(3));
+        assert!(params.to_map().len() == 2 && params.own.len() == 1);
     }
 
     #[test]
-    fn deser_field_full() {
-        let field_raw = "type: float\naka: ['δ']\noption: '--delta <delta>'\nbatch: {join: ','}";
-        let field: Field = serde_yaml::from_str(&field_raw).unwrap();
+    fn plan_output_collisions() {
+        let gen_raw = "
+            name: gen
+            bin: gen
+            format: '<graph>'
+            outputs:
+              sketch:
+                kind: file
+                path: 'sketches/<stem(graph)>.sk'
+            fields:
+              graph:
+                type: path
+            ";
+        let map = hashmap!{
+            "gen".to_string() => serde_yaml::from_str(gen_raw).unwrap(),
+        };
+        let plan = |graphs: &str| {
+            let exp: Experiment =
+                serde_yaml::from_str(&format!("jobs: [{{run: gen, parameters: {{graph: {}}}}}]",
+                                              graphs))
+                    .unwrap();
+            exp.plan(&map)
+        };
 
-        assert!(field ==
-                Field {
-            dtype: FieldType::Float,
-            aka: vec!["δ".to_string()],
-            option: Some("--delta <delta>".to_string()),
-            batch: BatchType::Join(",".to_string()),
-        });
+        assert!(plan("[data/a.bin, data/b.bin]").is_ok());
+        let err = plan("[data/a.bin, backup/a.bin]").unwrap_err();
+        assert!(err.kind().code() == "output_collision");
+        let message = err.to_string();
+        assert!(message.contains("gen #0") && message.contains("gen #1"));
+        assert!(message.contains("sketches/a.sk"));
     }
 
     #[test]
-    fn deser_output_simple() {
-        let out_raw = "msg: \"approximation ratio\"";
-        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+    fn plan_merges_constraints() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            ")
+            .unwrap();
+        let exp: Experiment = serde_yaml::from_str("
+            defaults:
+              constraints: [avx2, 'node[0-9]+']
+            jobs:
+              - run: est
+                parameters: {k: 1}
+                constraints: [a100, avx2]
+            ")
+            .unwrap();
 
-        assert!(out ==
-                Output {
-            msg: "approximation ratio".to_string(),
-            aka: vec![],
-        });
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog }).unwrap();
+        // the job's constraints add to the defaults, without repeating any
+        let expected = vec!["avx2".to_string(), "node[0-9]+".to_string(), "a100".to_string()];
+        assert!(plan[0].constraints == expected);
+
+        let json = serde_json::to_string(&plan[0]).unwrap();
+        let inst: JobInstance = serde_json::from_str(&json).unwrap();
+        assert!(inst.constraints == expected);
+        let json = json.replace(r#","constraints":["avx2","node[0-9]+","a100"]"#, "");
+        assert!(serde_json::from_str::<JobInstance>(&json).unwrap().constraints.is_empty());
     }
 
     #[test]
-    fn deser_output_full() {
-        let out_raw = "msg: approximation ratio\naka: ['ratio']";
-        let out: Output = serde_yaml::from_str(&out_raw).unwrap();
+    fn plan_applies_defaults() {
+        let prog = |name: &str| -> Program {
+            serde_yaml::from_str(&format!("
+                name: {0}
+                bin: {0}
+                format: '<k> --threads <threads>'
+                outputs: {{}}
+                fields:
+                  k:
+                    type: uint
+                ",
+                                          name))
+                .unwrap()
+        };
+        let exp_raw = "
+            defaults:
+              threads: 4
+              retries: 2
+              tags: [nightly]
+              constraints: [avx2]
+              env: {RUST_LOG: info}
+            jobs:
+              - run: est
+                parameters:
+                  k: 1
+              - run: big
+                parameters:
+                  k: 2
+                threads: 8
+                tags: [big]
+                constraints: [a100]
+                requires_files: ['idx/<k>.bin']
+                env: {RUST_LOG: debug}
+            ";
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
 
-        assert!(out ==
-                Output {
-            msg: "approximation ratio".to_string(),
-            aka: vec!["ratio".to_string()],
-        })
+        let mut plan = exp.plan(&hashmap!{
+                "est".to_string() => prog("est"),
+                "big".to_string() => prog("big"),
+            })
+            .unwrap();
+        plan.sort_by_key(|inst| inst.id);
+        assert!(plan[0].command == "est 1 --threads 4" && plan[0].retries == Some(2));
+        assert!(plan[0].tags == vec!["nightly".to_string()] && plan[0].env["RUST_LOG"] == "info");
+        assert!(plan[1].command == "big 2 --threads 8" && plan[1].retries == Some(2));
+        assert!(plan[1].tags == vec!["nightly".to_string(), "big".to_string()]);
+        assert!(plan[1].env["RUST_LOG"] == "debug");
+        assert!(plan[0].constraints == vec!["avx2".to_string()]);
+        assert!(plan[1].constraints == vec!["avx2".to_string(), "a100".to_string()]);
+        assert!(plan[0].requires_files.is_empty());
+        assert!(plan[1].requires_files == vec!["idx/2.bin".to_string()]);
+        let missing = missing_required_files(&plan, |path| path == "idx/2.bin");
+        assert!(missing.is_empty());
+        let missing = missing_required_files(&plan, |_| false);
+        assert!(missing ==
+                vec![Warning::MissingRequiredFile {
+                         job: "big".to_string(),
+                         path: "idx/2.bin".to_string(),
+                     }]);
+        assert!(missing[0].to_string() == "job big requires idx/2.bin, which does not exist");
     }
 
     #[test]
-    fn deser_problem_curv() {
-        let _prob: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+    fn plan_merges_hooks() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            hooks:
+              on_start: mkdir -p out
+              on_success: cp out/* /shared
+            ")
             .unwrap();
+        let exp_raw = "
+            defaults:
+              hooks:
+                on_success: rsync -a out/ /shared
+                on_failure: echo failed >> failures.log
+            jobs:
+              - run: est
+                parameters:
+                  k: 1
+            ";
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        let map = hashmap!{ "est".to_string() => prog };
+        let plan = exp.plan(&map).unwrap();
+        assert!(program_headers(&plan, &map)[0].hooks.merge(&plan[0].hooks) ==
+                Hooks {
+                    on_start: Some("mkdir -p out".to_string()),
+                    on_success: Some("rsync -a out/ /shared".to_string()),
+                    on_failure: Some("echo failed >> failures.log".to_string()),
+                });
     }
 
     #[test]
-    fn deser_problem_interdict() {
-        let _prob: Program =
-            serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap()).unwrap();
+    fn plan_computes_threads() {
+        let prog_raw = "
+            name: est
+            bin: est
+            format: '<k> --threads <threads>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            ";
+        let exp_raw = "
+            jobs:
+              - run: est
+                parameters:
+                  k: [2, 5, 16]
+                threads: <k> / 2
+                max_threads: 4
+                memory: threads * 1.5
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog }).unwrap();
+        let mut threads = plan.iter()
+            .map(|inst| (inst.threads, inst.memory.unwrap()))
+            .collect::<Vec<_>>();
+        threads.sort_by_key(|&(t, _)| t);
+        assert!(threads == vec![(1, 1.5), (3, 4.5), (4, 6.0)]);
     }
 
     #[test]
-    fn deser_problem_interdict_validate() {
-        let _prob: Program =
+    fn select_by_tags() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+            .unwrap();
+        let validate: Program =
             serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
                 .unwrap();
+        let mut exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+        exp.jobs[0].tags = vec!["baseline".to_string()];
+        exp.jobs[1].tags = vec!["validation".to_string()];
+        let map = hashmap!{
+            "interdict".to_string() => prog,
+            "interdict-validate".to_string() => validate,
+        };
+
+        let selected = select_tags(exp.plan(&map).unwrap(), &["validation".to_string()], &[]);
+        assert!(selected.len() == 660);
+        let selected = select_tags(exp.plan(&map).unwrap(), &["baseline".to_string()], &[]);
+        assert!(selected.len() == 330);
+        let selected = select_tags(exp.plan(&map).unwrap(), &[], &["baseline".to_string()]);
+        assert!(selected.is_empty());
     }
 
     #[test]
-    fn deser_exp_curv() {
-        let _prob: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+    fn select_stale_outputs() {
+        let gen_raw = "
+            name: gen
+            bin: gen
+            format: '<graph>'
+            outputs:
+              sketch:
+                kind: file
+                path: 'sketches/<stem(graph)>.sk'
+            fields:
+              graph:
+                type: path
+            ";
+        let use_raw = "
+            name: use
+            bin: use
+            format: '<sketch>'
+            outputs:
+              result:
+                kind: file
+                path: 'results/<stem(sketch)>.out'
+            fields:
+              sketch:
+                type: path
+            ";
+        let exp_raw = "
+            jobs:
+              - run: gen
+                parameters: {graph: [data/a.bin, data/b.bin, data/c.bin]}
+              - run: use
+                on_each: [gen]
+            ";
+        let map = hashmap!{
+            "gen".to_string() => serde_yaml::from_str(gen_raw).unwrap(),
+            "use".to_string() => serde_yaml::from_str(use_raw).unwrap(),
+        };
+        let exp: Experiment = serde_yaml::from_str(exp_raw).unwrap();
+        let plan = exp.plan(&map).unwrap();
+
+        // a is up to date, b's graph changed after it was sketched, and c was never used
+        let times = hashmap!{
+            "data/a.bin" => 1,
+            "sketches/a.sk" => 2,
+            "results/a.out" => 3,
+            "data/b.bin" => 5,
+            "sketches/b.sk" => 2,
+            "results/b.out" => 3,
+            "data/c.bin" => 1,
+            "sketches/c.sk" => 2,
+        };
+        let modified = |path: &str| {
+            times.get(path).map(|&secs| UNIX_EPOCH + Duration::from_secs(secs))
+        };
+        let mut stale = select_stale(plan, &map, modified)
+            .into_iter()
+            .map(|inst| (inst.command, inst.depends.len()))
+            .collect::<Vec<_>>();
+        stale.sort();
+        assert!(stale ==
+                vec![("gen data/b.bin".to_string(), 0),
+                     ("use sketches/b.sk".to_string(), 1),
+                     ("use sketches/c.sk".to_string(), 0)]);
     }
 
     #[test]
-    fn deser_exp_interdict() {
-        let _prob: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+    fn sample_stratified() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp_raw = "
+            jobs:
+              - run: curv
+                parameters:
+                  problem: cover
+                  graph: [a.bin, b.bin, c.bin]
+                  k: {from: 1, to: 10, step: 1}
+                  delta: 0.1
+                  delta2: 0.01
+                  epsilon: 0.1
+        ";
+        let exp: Experiment = serde_yaml::from_str(exp_raw).unwrap();
+        let map = hashmap!{ "curv".to_string() => prog };
+
+        let sample = sample_plan(exp.plan(&map).unwrap(), 6, Some("graph"), 7);
+        assert!(sample.len() == 6);
+        for graph in &["a.bin", "b.bin", "c.bin"] {
+            let datum = FieldData::Str(graph.to_string());
+            assert!(sample.iter().filter(|inst| inst.params["graph"] == datum).count() == 2);
+        }
+
+        let ids = |plan: Vec<JobInstance>| plan.iter().map(|inst| inst.id).collect::<Vec<_>>();
+        assert!(ids(sample_plan(exp.plan(&map).unwrap(), 4, None, 7)) ==
+                ids(sample_plan(exp.plan(&map).unwrap(), 4, None, 7)));
+        assert!(sample_plan(exp.plan(&map).unwrap(), 100, None, 7).len() == 30);
     }
 
     #[test]
-    fn fill_no_option() {
-        let field = Field {
-            dtype: FieldType::UInt,
-            aka: vec![],
-            batch: BatchType::None,
-            option: None,
-        };
+    fn store_outputs_by_hash() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let mut exp: Experiment =
+            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+        let map = hashmap!{ "curv".to_string() => prog };
+        let dir = ::std::env::temp_dir().join("waluigi-store-test");
+        let _ = fs::remove_dir_all(&dir);
+        exp.set_store(&dir.to_string_lossy());
 
-        assert!(field.fill_with(&FieldData::UInt(27)).unwrap() == "27".to_string());
+        let plan = exp.plan(&map).unwrap();
+        write_store_manifest(&plan, &dir).unwrap();
+        let expected = dir.join(&plan[0].hash).to_string_lossy().into_owned();
+        assert!(plan[0].output_dir == Some(expected));
+
+        // planning again keeps the manifest as it was
+        write_store_manifest(&exp.plan(&map).unwrap(), &dir).unwrap();
+        let manifest: BTreeMap<String, StoreEntry> =
+            serde_json::from_reader(File::open(dir.join(STORE_MANIFEST)).unwrap()).unwrap();
+        assert!(manifest.len() == plan.len());
+        assert!(manifest[&plan[0].hash].program == "curv");
+        assert!(manifest[&plan[0].hash].params["graph"] == plan[0].params["graph"]);
     }
 
     #[test]
-    fn fill_option_bool() {
-        let field = Field {
-            dtype: FieldType::Bool,
-            aka: vec![],
-            batch: BatchType::None,
-            option: Some("--flag".to_string()),
-        };
+    fn plan_renders_labels() {
+        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+            .unwrap();
+        let exp_raw = "
+            defaults:
+              label: '<program>-<id>'
+            jobs:
+              - run: curv
+                parameters:
+                  problem: cover
+                  graph: a.bin
+                  k: 10
+                  delta: 0.1
+                  delta2: 0.01
+                  epsilon: 0.1
+                repetitions: 2
+                label: 'curv <graph> k=<k> rep=<repetition> <missing>'
+            ";
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
 
-        assert!(field.fill_with(&FieldData::Bool(true)).unwrap() == "--flag".to_string());
-        assert!(field.fill_with(&FieldData::Bool(false)).unwrap() == "".to_string());
+        let mut labels = exp.plan(&hashmap!{ "curv".to_string() => prog })
+            .unwrap()
+            .into_iter()
+            .map(|inst| inst.label.unwrap())
+            .collect::<Vec<_>>();
+        labels.sort();
+        assert!(labels ==
+                vec!["curv a.bin k=10 rep=0 <missing>".to_string(),
+                     "curv a.bin k=10 rep=1 <missing>".to_string()]);
     }
 
     #[test]
-    fn fill_option_value() {
-        let field = Field {
-            dtype: FieldType::Float,
-            aka: vec![],
-            batch: BatchType::None,
-            option: Some("--float <foo>".to_string()),
+    fn plan_threads_field() {
+        let prog_raw = "
+            name: par
+            bin: par
+            format: '<graph>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              threads:
+                type: uint
+                option: '-t <threads>'
+            ";
+        let progs = hashmap!{ "par".to_string() => serde_yaml::from_str(&prog_raw).unwrap() };
+        let plan = |exp_raw: &str| {
+            let exp: Experiment = serde_yaml::from_str(exp_raw).unwrap();
+            exp.plan(&progs).unwrap().remove(0)
         };
 
-        println!("{}", field.fill_with(&FieldData::Float(0.27)).unwrap());
-        assert!(field.fill_with(&FieldData::Float(0.27)).unwrap() == "--float 0.27".to_string());
+        let inst = plan("jobs: [{run: par, parameters: {graph: a.bin}, threads: 4}]");
+        assert!(inst.command == "par a.bin -t 4" && inst.threads == 4);
+        let inst = plan("jobs: [{run: par, parameters: {graph: a.bin, threads: 2}}]");
+        assert!(inst.command == "par a.bin -t 2" && inst.threads == 2);
     }
 
     #[test]
-    fn job_validate_curv() {
+    fn plan_value_labels() {
         let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
             .unwrap();
-        let exp: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+        let exp_raw = "
+            jobs:
+              - run: curv
+                parameters:
+                  problem: cover
+                  graph:
+                    - {value: data/huge-graph-v3.bin, label: huge}
+                    - data/small.bin
+                  k: 10
+                  delta: 0.1
+                  delta2: 0.01
+                  epsilon: 0.1
+                label: 'curv <graph>'
+                output_dir: 'out/<graph>/<k>'
+            ";
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let mut plan = exp.plan(&hashmap!{ "curv".to_string() => prog }).unwrap();
+        plan.sort_by_key(|inst| inst.label.clone());
 
-        for job in &exp.jobs {
-            prog.validate_parameters(&job.parameters).unwrap();
-        }
+        assert!(plan[0].label == Some("curv data/small.bin".to_string()));
+        assert!(plan[0].value_labels.is_empty());
+        assert!(plan[1].label == Some("curv huge".to_string()));
+        assert!(plan[1].output_dir == Some("out/huge/<k>".to_string()));
+        assert!(plan[1].params["graph"] == FieldData::Str("data/huge-graph-v3.bin".to_string()));
+        assert!(plan[1].command.contains("data/huge-graph-v3.bin"));
     }
 
     #[test]
-    #[should_panic]
-    fn job_invalid_curv() {
-        let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
+    fn read_value_files() {
+        let exp_raw = "
+            jobs:
+              - run: interdict
+                parameters:
+                  k: {file: k.txt}
+                  graph: {file: graphs.csv, column: graph}
+            ";
+        let mut exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let dir = ::std::env::temp_dir().join("waluigi-values-test");
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("k.txt")).unwrap().write_all(b"# k\n10\n\n20\n").unwrap();
+        File::create(dir.join("graphs.csv")).unwrap().write_all(b"name,graph\nk,a.bin\n").unwrap();
+
+        exp.read_value_files(&dir).unwrap();
+        let k = exp.jobs[0].parameters["k"].vectorize("k").unwrap();
+        assert!(k == vec![FieldData::UInt(10), FieldData::UInt(20)]);
+        let graph = exp.jobs[0].parameters["graph"].vectorize("graph").unwrap();
+        assert!(graph == vec![FieldData::Str("a.bin".to_string())]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn vectorize_ranges() {
+        let range = |raw: &str| serde_yaml::from_str::<FieldSetting>(raw).unwrap().vectorize("k");
+        assert!(range("{from: 0, to: 4, step: 2}").unwrap() ==
+                vec![FieldData::UInt(0), FieldData::UInt(2), FieldData::UInt(4)]);
+        assert!(range("{from: 0, to: 1, step: 0.5}").unwrap() ==
+                vec![FieldData::Float(0.0), FieldData::Float(0.5), FieldData::Float(1.0)]);
+
+        let err = range("{from: 0, to: ten, step: 2}").unwrap_err();
+        assert!(err.kind().code() == "invalid_range");
+        assert!(err.to_string().contains(" for k "));
+
+        // steps that never reach the end would loop forever
+        assert!(range("{from: 0, to: 4, step: 0}").unwrap_err().kind().code() == "invalid_step");
+        assert!(range("{from: 0, to: 1, step: 0.0}").unwrap_err().kind().code() == "invalid_step");
+        assert!(range("{from: 0, to: 1, step: -0.5}").unwrap_err().kind().code() ==
+                "invalid_step");
+        assert!(range("{from: 18446744073709551614, to: 18446744073709551615, step: 2}")
+            .unwrap() == vec![FieldData::UInt(18446744073709551614)]);
+    }
+
+    #[test]
+    fn plan_matrix() {
+        let prog_raw = "
+            name: est
+            bin: est
+            format: '<graph> <k> <epsilon>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              k:
+                type: uint
+              epsilon:
+                type: float
+            ";
+        let exp_raw = "
+            jobs:
+              - run: est
+                parameters:
+                  epsilon: [0.1, 0.2]
+                matrix:
+                  file: design.tsv
+                  columns: {size: k}
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let mut exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let dir = ::std::env::temp_dir().join("waluigi-matrix-test");
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join("design.tsv"))
+            .unwrap()
+            .write_all(b"graph\tsize\n10.bin\t10\nb.bin\t20\n")
             .unwrap();
-        let exp: Experiment =
+        exp.read_value_files(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let map = hashmap!{ "est".to_string() => prog };
+        let mut commands = exp.plan(&map)
+            .unwrap()
+            .into_iter()
+            .map(|inst| inst.command)
+            .collect::<Vec<_>>();
+        commands.sort();
+        assert!(commands ==
+                vec!["est 10.bin 10 0.1".to_string(),
+                     "est 10.bin 10 0.2".to_string(),
+                     "est b.bin 20 0.1".to_string(),
+                     "est b.bin 20 0.2".to_string()]);
+
+        exp.jobs[0].matrix.as_mut().unwrap().rows[0][1] = "ten".to_string();
+        assert!(exp.plan(&map).is_err());
+    }
+
+    #[test]
+    fn set_parameters() {
+        let mut exp: Experiment =
             serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
 
-        for job in &exp.jobs {
-            prog.validate_parameters(&job.parameters).unwrap();
-        }
+        exp.set_parameter(None, "epsilon", FieldSetting::Value(FieldData::Float(0.5))).unwrap();
+        exp.set_parameter(Some("interdict"), "k", FieldSetting::Value(FieldData::UInt(5)))
+            .unwrap();
+        assert!(exp.jobs.iter().all(|job| {
+            job.parameters["epsilon"].vectorize("epsilon").unwrap() == vec![FieldData::Float(0.5)]
+        }));
+        assert!(exp.jobs[0].batch().unwrap().len() == 30);
+        assert!(exp.set_parameter(None, "nope", FieldSetting::List(vec![])).is_err());
+        assert!(exp.set_parameter(Some("curv"), "k", FieldSetting::List(vec![])).is_err());
     }
 
     #[test]
-    fn job_batch_curv() {
-        let exp: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+    fn shared_parameters() {
+        let exp_raw = "
+            shared_parameters:
+              graphs:
+                graph: [a.bin, b.bin]
+                k: 10
+              small:
+                k: 5
+            jobs:
+              - run: curv
+                use: [graphs]
+                parameters: {k: 20}
+              - run: interdict
+                use: [graphs, small]
+              - run: other
+                use: [nope]
+            ";
+        let mut exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let err = exp.apply_shared_parameters().unwrap_err();
+        assert!(err.kind().code() == "unknown_shared_parameters");
 
-        for job in &exp.jobs {
-            let batch = job.batch().unwrap();
-            assert!(batch.len() == 2310)
-        }
+        exp.jobs.pop();
+        exp.apply_shared_parameters().unwrap();
+        assert!(exp.jobs[0].parameters["graph"].vectorize("graph").unwrap().len() == 2);
+        assert!(exp.jobs[0].parameters["k"].vectorize("k").unwrap() == vec![FieldData::UInt(20)]);
+        assert!(exp.jobs[1].parameters["graph"].vectorize("graph").unwrap().len() == 2);
+        assert!(exp.jobs[1].parameters["k"].vectorize("k").unwrap() == vec![FieldData::UInt(5)]);
     }
 
     #[test]
-    fn job_batch_interdict() {
-        let exp: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
+    fn apply_vars() {
+        let exp_raw = "
+            vars:
+              data: data/small
+              max_k: 20
+            jobs:
+              - run: interdict
+                parameters:
+                  graph: ['<data>/a.bin', '<missing>/b.bin']
+                  k: {from: 0, to: <max_k>, step: 10}
+            ";
+        let mut exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        exp.set_var("data", FieldData::Str("data/large".to_string())).unwrap();
+        assert!(exp.set_var("nope", FieldData::UInt(1)).is_err());
+        exp.apply_vars();
 
-        for (job, &size) in exp.jobs.iter().zip(&vec![330, 1]) {
-            let batch = job.batch().unwrap();
-            println!("{} {}", batch.len(), size);
-            assert!(batch.len() == size)
-        }
+        assert!(exp.jobs[0].parameters["graph"].vectorize("graph").unwrap() ==
+                vec![FieldData::Str("data/large/a.bin".to_string()),
+                     FieldData::Str("<missing>/b.bin".to_string())]);
+        assert!(exp.jobs[0].parameters["k"].vectorize("k").unwrap() ==
+                vec![FieldData::UInt(0), FieldData::UInt(10), FieldData::UInt(20)]);
     }
 
     #[test]
-    fn plan_curv() {
+    fn plan_random_search() {
         let prog: Program = serde_yaml::from_reader(File::open("programs/curv.yaml").unwrap())
             .unwrap();
-        let exp: Experiment =
+        let mut exp: Experiment =
             serde_yaml::from_reader(File::open("programs/exp-curv.yaml").unwrap()).unwrap();
+        let map = hashmap!{ "curv".to_string() => prog };
 
-        let map = hashmap!{
-            "curv".to_string() => prog,
+        let hashes = |exp: &Experiment| {
+            let mut hashes = exp.plan(&map)
+                .unwrap()
+                .into_iter()
+                .map(|inst| inst.hash)
+                .collect::<Vec<_>>();
+            hashes.sort();
+            hashes
         };
+        exp.search = Some(serde_yaml::from_str("{type: random, budget: 50, seed: 7}").unwrap());
+        let first = hashes(&exp);
+        assert!(first.len() == 50);
+        assert!(first == hashes(&exp));
 
-        assert!(exp.plan(6, &map).unwrap().len() == 2310);
+        exp.search = Some(Search::Random {
+            budget: 50,
+            seed: 8,
+        });
+        assert!(first != hashes(&exp));
     }
 
     #[test]
-    fn plan_interdict() {
-        let prog: Program = serde_yaml::from_reader(File::open("programs/interdict.yaml").unwrap())
+    fn plan_one_at_a_time() {
+        let prog_raw = "
+            name: est
+            bin: est
+            format: '<k> <epsilon> <graph>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              k:
+                type: uint
+              epsilon:
+                type: float
+            ";
+        let exp_raw = "
+            jobs:
+              - run: est
+                design: oat
+                baseline:
+                  k: 2
+                  graph: a.bin
+                parameters:
+                  k: [1, 2, 3]
+                  epsilon: [0.1, 0.2]
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        let mut commands = exp.plan(&hashmap!{ "est".to_string() => prog })
+            .unwrap()
+            .into_iter()
+            .map(|inst| inst.command)
+            .collect::<Vec<_>>();
+        commands.sort();
+        assert!(commands ==
+                vec!["est 1 0.1 a.bin".to_string(),
+                     "est 2 0.1 a.bin".to_string(),
+                     "est 2 0.2 a.bin".to_string(),
+                     "est 3 0.1 a.bin".to_string()]);
+    }
+
+    #[test]
+    fn plan_exclude_include() {
+        let prog_raw = "
+            name: est
+            bin: est
+            format: '<k> <epsilon>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+              epsilon:
+                type: float
+            ";
+        let exp_raw = "
+            jobs:
+              - run: est
+                parameters:
+                  k: [1, 2]
+                  epsilon: [0.1, 0.2]
+                exclude:
+                  - {k: 2, epsilon: 0.2}
+                include:
+                  - {k: 100}
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+
+        let mut commands = exp.plan(&hashmap!{ "est".to_string() => prog })
+            .unwrap()
+            .into_iter()
+            .map(|inst| inst.command)
+            .collect::<Vec<_>>();
+        commands.sort();
+        assert!(commands ==
+                vec!["est 1 0.1".to_string(),
+                     "est 1 0.2".to_string(),
+                     "est 100 0.1".to_string(),
+                     "est 2 0.1".to_string()]);
+    }
+
+    #[test]
+    fn plan_paired_seeds() {
+        let prog = |name: &str| -> Program {
+            serde_yaml::from_str(&format!("
+                name: {0}
+                bin: {0}
+                format: '<k> --seed <seed>'
+                outputs: {{}}
+                fields:
+                  k:
+                    type: uint
+                  seed:
+                    type: uint
+                ",
+                                          name))
+                .unwrap()
+        };
+        let exp_raw = "
+            jobs:
+              - run: greedy
+                parameters: {k: 10}
+                repetitions: 5
+                seeds: {field: seed, group: cmp}
+              - run: exact
+                parameters: {k: [10, 20]}
+                repetitions: 5
+                seeds: {field: seed, group: cmp}
+            ";
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let plan = exp.plan(&hashmap!{
+                "greedy".to_string() => prog("greedy"),
+                "exact".to_string() => prog("exact"),
+            })
             .unwrap();
-        let validate: Program =
-            serde_yaml::from_reader(File::open("programs/interdict-validate.yaml").unwrap())
-                .unwrap();
-        let exp: Experiment =
-            serde_yaml::from_reader(File::open("programs/exp-interdict.yaml").unwrap()).unwrap();
 
-        let map = hashmap!{
-            "interdict".to_string() => prog,
-            "interdict-validate".to_string() => validate,
+        let seeds = |program: &str| {
+            let mut seeds = plan.iter()
+                .filter(|inst| inst.program == program && inst.params["k"] == FieldData::UInt(10))
+                .map(|inst| {
+                    (inst.params[&format!("repetition-{}", program)].to_string(),
+                     inst.params["seed"].clone())
+                })
+                .collect::<Vec<_>>();
+            seeds.sort_by_key(|&(ref rep, _)| rep.clone());
+            seeds.into_iter().map(|(_, seed)| seed).collect::<Vec<_>>()
         };
+        let greedy = seeds("greedy");
+        assert!(greedy.len() == 5 && greedy == seeds("exact"));
+    }
+
+    #[test]
+    fn plan_repetition_field() {
+        let prog_raw = "
+            name: rep
+            bin: rep
+            format: '<graph> --run <rep>'
+            outputs: {}
+            fields:
+              graph:
+                type: path
+              rep:
+                type: uint
+            ";
+        let exp_raw = "
+            jobs:
+              - run: rep
+                parameters: {graph: a.bin}
+                repetitions: 3
+                repetition_field: rep
+            ";
+        let prog: Program = serde_yaml::from_str(&prog_raw).unwrap();
+        let exp: Experiment = serde_yaml::from_str(&exp_raw).unwrap();
+        let mut commands = exp.plan(&hashmap!{ "rep".to_string() => prog })
+            .unwrap()
+            .into_iter()
+            .map(|inst| inst.command)
+            .collect::<Vec<_>>();
+        commands.sort();
 
-        assert!(exp.plan(6, &map).unwrap().len() == 660);
+        assert!(commands ==
+                vec!["rep a.bin --run 0".to_string(),
+                     "rep a.bin --run 1".to_string(),
+                     "rep a.bin --run 2".to_string()]);
     }
 }
@@ -0,0 +1,275 @@
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use std::path::Path;
+
+/// Splits a placeholder such as `round(epsilon, 3)` into the name of a helper function and its
+/// arguments.
+fn call_parts(name: &str) -> Option<(&str, Vec<&str>)> {
+    match (name.find('('), name.ends_with(')')) {
+        (Some(open), true) if open > 0 => {
+            let args = &name[open + 1..name.len() - 1];
+            Some((name[..open].trim(), args.split(',').map(|arg| arg.trim()).collect()))
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates a helper function placeholder, looking its arguments up as placeholder names:
+///
+/// - `basename(x)` and `dirname(x)`, the last component of a path and the rest of it,
+/// - `stem(x)`, the last component of a path without its extension,
+/// - `round(x, digits)`, a number rounded to a literal number of decimal digits,
+/// - `hash(x, ...)`, a short hash of the values, e.g. `hash(params)` for a name unique to the
+///   parameters of a job.
+///
+/// Returns `None` for unknown functions, or if an argument cannot be looked up.
+fn call<F>(name: &str, lookup: &F) -> Option<String>
+    where F: Fn(&str) -> Option<String>
+{
+    let (func, args) = match call_parts(name) {
+        Some(parts) => parts,
+        None => return None,
+    };
+    let path = || lookup(args[0]).map(|value| Path::new(&value).to_path_buf());
+    match (func, args.len()) {
+        ("basename", 1) => {
+            path().and_then(|p| p.file_name().map(|s| s.to_string_lossy().into_owned()))
+        }
+        ("dirname", 1) => {
+            path().and_then(|p| p.parent().map(|s| s.to_string_lossy().into_owned()))
+        }
+        ("stem", 1) => {
+            path().and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        }
+        ("round", 2) => {
+            let value = lookup(args[0]).and_then(|value| value.parse::<f64>().ok());
+            match (value, args[1].parse::<usize>()) {
+                (Some(value), Ok(digits)) => Some(format!("{:.*}", digits, value)),
+                _ => None,
+            }
+        }
+        ("hash", _) => {
+            let values = args.iter().map(|arg| lookup(arg)).collect::<Option<Vec<_>>>();
+            values.map(|values| {
+                let mut hasher = Sha256::new();
+                hasher.input_str(&values.join("\0"));
+                hasher.result_str()[..8].to_string()
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    /// The name between the angle brackets of a `<name>` placeholder.
+    Placeholder(String),
+}
+
+/// A command template such as `--bias <bias>`, parsed once into literal and placeholder segments
+/// so that filling it is a single concatenation rather than a search-and-replace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Template {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut rest = source;
+        while let Some(open) = rest.find('<') {
+            // placeholders are non-empty and cannot contain another `<`
+            let close = rest[open + 1..].find('>');
+            let reopen = rest[open + 1..].find('<');
+            match close {
+                Some(len) if len > 0 && reopen.map_or(true, |r| r > len) => {
+                    literal.push_str(&rest[..open]);
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(literal.clone()));
+                        literal.clear();
+                    }
+                    segments.push(Segment::Placeholder(rest[open + 1..open + 1 + len].to_string()));
+                    rest = &rest[open + len + 2..];
+                }
+                _ => {
+                    literal.push_str(&rest[..open + 1]);
+                    rest = &rest[open + 1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Template {
+            source: source.to_string(),
+            segments: segments,
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The names the template refers to, whether as placeholders or as arguments of helper
+    /// functions.
+    pub fn references(&self) -> Vec<&str> {
+        let mut names = vec![];
+        for segment in &self.segments {
+            if let &Segment::Placeholder(ref name) = segment {
+                match call_parts(name) {
+                    Some((_, args)) => names.extend(args),
+                    None => names.push(name.as_str()),
+                }
+            }
+        }
+        names
+    }
+
+    /// The template with the placeholders for which `skip(name)` holds taken out, along with the
+    /// whitespace that separated each from what came before it (or after it, if it came first).
+    pub fn without<F>(&self, skip: F) -> Template
+        where F: Fn(&str) -> bool
+    {
+        let mut segments = vec![];
+        let mut trim_next = false;
+        for segment in &self.segments {
+            match segment {
+                &Segment::Placeholder(ref name) if skip(name) => {
+                    if let Some(&mut Segment::Literal(ref mut s)) = segments.last_mut() {
+                        let len = s.trim_right().len();
+                        s.truncate(len);
+                    }
+                    if segments.last() == Some(&Segment::Literal(String::new())) {
+                        segments.pop();
+                    }
+                    trim_next = segments.is_empty();
+                }
+                &Segment::Literal(ref s) if trim_next => {
+                    trim_next = false;
+                    if !s.trim_left().is_empty() {
+                        segments.push(Segment::Literal(s.trim_left().to_string()));
+                    }
+                }
+                _ => {
+                    trim_next = false;
+                    segments.push(segment.clone());
+                }
+            }
+        }
+
+        let source = segments.iter()
+            .map(|segment| match segment {
+                &Segment::Literal(ref s) => s.clone(),
+                &Segment::Placeholder(ref name) => format!("<{}>", name),
+            })
+            .collect();
+        Template {
+            source: source,
+            segments: segments,
+        }
+    }
+
+    /// Renders the template, filling each placeholder with `lookup(name)`, or the result of the
+    /// helper function it calls (see `call`). Placeholders for which `lookup` returns `None` are
+    /// left in place so they can be filled later.
+    pub fn render<F>(&self, lookup: F) -> String
+        where F: Fn(&str) -> Option<String>
+    {
+        let mut out = String::with_capacity(self.source.len());
+        for segment in &self.segments {
+            match segment {
+                &Segment::Literal(ref s) => out.push_str(s),
+                &Segment::Placeholder(ref name) => {
+                    match lookup(name).or_else(|| call(name, &lookup)) {
+                        Some(value) => out.push_str(&value),
+                        None => {
+                            out.push('<');
+                            out.push_str(name);
+                            out.push('>');
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl ToString for Template {
+    fn to_string(&self) -> String {
+        self.source.clone()
+    }
+}
+
+impl Serialize for Template {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl Deserialize for Template {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer
+    {
+        String::deserialize(deserializer).map(|source| Template::parse(&source))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_segments() {
+        let template = Template::parse("--bias <bias> < 3 <>");
+        assert!(template.segments() ==
+                &[Segment::Literal("--bias ".to_string()),
+                  Segment::Placeholder("bias".to_string()),
+                  Segment::Literal(" < 3 <>".to_string())]);
+    }
+
+    #[test]
+    fn render_leaves_unknown() {
+        let template = Template::parse("<graph> <k> --threads <threads>");
+        let rendered = template.render(|name| match name {
+            "graph" => Some("a.bin".to_string()),
+            "k" => Some("10".to_string()),
+            _ => None,
+        });
+        assert!(rendered == "a.bin 10 --threads <threads>");
+    }
+
+    #[test]
+    fn without_separators() {
+        let template = Template::parse("<a> <b> --c=<c> <d>");
+        assert!(template.without(|name| name == "b").to_string() == "<a> --c=<c> <d>");
+        assert!(template.without(|name| name == "a").to_string() == "<b> --c=<c> <d>");
+        assert!(template.without(|name| name == "d").to_string() == "<a> <b> --c=<c>");
+        assert!(template.without(|name| name != "c").to_string() == "--c=<c>");
+    }
+
+    #[test]
+    fn render_helpers() {
+        let template = Template::parse("<stem(graph)>/<basename(graph)>-<round(epsilon, 2)> \
+                                        <dirname(graph)> <hash(graph, epsilon)> <upper(graph)>");
+        let lookup = |name: &str| match name {
+            "graph" => Some("data/road-ny.bin".to_string()),
+            "epsilon" => Some("0.1234".to_string()),
+            _ => None,
+        };
+        let rendered = template.render(&lookup);
+        let hash = template.render(&lookup).split(' ').nth(2).unwrap().to_string();
+        assert!(rendered == format!("road-ny/road-ny.bin-0.12 data {} <upper(graph)>", hash));
+        assert!(hash.len() == 8);
+        assert!(template.references().contains(&"epsilon"));
+    }
+}
@@ -0,0 +1,359 @@
+/// Reads a number followed by unit suffixes, such as `2h30m`, into the sum of each number times
+/// the scale of its unit. Units are matched case-sensitively.
+fn parse_suffixed(text: &str, scale: &Fn(&str) -> Option<f64>) -> Option<f64> {
+    let mut total = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_digit(10) && c != '.').unwrap_or(rest.len());
+        let unit_len = rest[number_len..]
+            .find(|c: char| c.is_digit(10) || c == '.')
+            .unwrap_or(rest.len() - number_len);
+        match (rest[..number_len].parse::<f64>(), scale(&rest[number_len..number_len + unit_len])) {
+            (Ok(number), Some(scale)) => total += number * scale,
+            _ => return None,
+        }
+        rest = &rest[number_len + unit_len..];
+    }
+    Some(total)
+}
+
+/// Reads a duration such as `5m`, `2h30m`, `1.5h` or `00:30:00` into seconds. Bare numbers are
+/// taken to be seconds.
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let secs = if text.contains(':') {
+        let parts = text.split(':')
+            .map(|part| part.parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>();
+        match parts {
+            Some(ref parts) if parts.len() <= 3 => {
+                Some(parts.iter().fold(0.0, |total, part| total * 60.0 + part))
+            }
+            _ => None,
+        }
+    } else if text.is_empty() {
+        None
+    } else {
+        parse_suffixed(text, &|unit| match unit {
+            "" | "s" => Some(1.0),
+            "m" => Some(60.0),
+            "h" => Some(3600.0),
+            "d" => Some(86400.0),
+            _ => None,
+        })
+    };
+    secs.and_then(|secs| if secs >= 0.0 && secs.is_finite() {
+        Some(secs)
+    } else {
+        None
+    })
+}
+
+/// Writes `secs` as `seconds` (the default) or as `hms`, `HH:MM:SS` rounded to the second.
+pub fn render_duration(secs: f64, render: Option<&str>) -> Option<String> {
+    match render {
+        None | Some("seconds") => Some(render_number(secs)),
+        Some("hms") => {
+            let total = secs.round() as u64;
+            Some(format!("{:02}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60))
+        }
+        _ => None,
+    }
+}
+
+/// Reads a size such as `512M`, `2G` or `1.5GiB` into bytes. Single-letter and `iB` suffixes are
+/// powers of 1024, `KB`, `MB` and so on powers of 1000, and bare numbers are bytes.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let bytes = parse_suffixed(text, &|unit| {
+        let (power, base) = match unit {
+            "" | "B" => (0, 1024.0),
+            "K" | "KiB" => (1, 1024.0),
+            "M" | "MiB" => (2, 1024.0),
+            "G" | "GiB" => (3, 1024.0),
+            "T" | "TiB" => (4, 1024.0),
+            "KB" => (1, 1000.0),
+            "MB" => (2, 1000.0),
+            "GB" => (3, 1000.0),
+            "TB" => (4, 1000.0),
+            _ => return None,
+        };
+        Some(f64::powi(base, power))
+    });
+    bytes.map(|bytes| bytes.round() as u64)
+}
+
+/// Writes a number without a fractional part if it has none.
+fn render_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        (value as u64).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes `bytes` as `bytes` (the default), or in `kib`, `mib` or `gib`.
+pub fn render_size(bytes: u64, render: Option<&str>) -> Option<String> {
+    let power = match render {
+        None | Some("bytes") => 0,
+        Some("kib") => 1,
+        Some("mib") => 2,
+        Some("gib") => 3,
+        _ => return None,
+    };
+    Some(render_number(bytes as f64 / f64::powi(1024.0, power)))
+}
+
+/// A date and time read from RFC 3339 text, kept in the offset it was written with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// Digits of the fractional second, as written.
+    fraction: String,
+    /// Offset from UTC in minutes, if one was given.
+    offset: Option<i64>,
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    era * 146097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719468
+}
+
+/// Reads the unsigned number in `text[start..end]`, which must be all digits.
+fn digits(text: &str, start: usize, end: usize) -> Option<u32> {
+    text.get(start..end).and_then(|part| if part.chars().all(|c| c.is_digit(10)) {
+        part.parse().ok()
+    } else {
+        None
+    })
+}
+
+impl DateTime {
+    /// Reads an RFC 3339 timestamp such as `2017-03-01T12:00:00Z`. The offset may be left out,
+    /// and so may the time, for programs that take dates.
+    pub fn parse(text: &str) -> Option<DateTime> {
+        let text = text.trim();
+        if text.get(4..5) != Some("-") || text.get(7..8) != Some("-") {
+            return None;
+        }
+        let mut dt = DateTime {
+            year: match digits(text, 0, 4) {
+                Some(year) => year as i64,
+                None => return None,
+            },
+            month: match digits(text, 5, 7) {
+                Some(month) if month >= 1 && month <= 12 => month,
+                _ => return None,
+            },
+            day: match digits(text, 8, 10) {
+                Some(day) => day,
+                None => return None,
+            },
+            hour: 0,
+            minute: 0,
+            second: 0,
+            fraction: String::new(),
+            offset: None,
+        };
+        if dt.day < 1 || dt.day > days_in_month(dt.year, dt.month) {
+            return None;
+        }
+
+        let mut rest = &text[10..];
+        if rest.is_empty() {
+            return Some(dt);
+        }
+        let separated = rest.starts_with(|c: char| c == 'T' || c == 't' || c == ' ');
+        if !separated || rest.get(3..4) != Some(":") || rest.get(6..7) != Some(":") {
+            return None;
+        }
+        match (digits(rest, 1, 3), digits(rest, 4, 6), digits(rest, 7, 9)) {
+            (Some(hour), Some(minute), Some(second)) if hour < 24 && minute < 60 &&
+                                                       second <= 60 => {
+                dt.hour = hour;
+                dt.minute = minute;
+                dt.second = second;
+            }
+            _ => return None,
+        }
+        rest = &rest[9..];
+
+        if rest.starts_with('.') {
+            let len = rest[1..].find(|c: char| !c.is_digit(10)).unwrap_or(rest.len() - 1);
+            if len == 0 {
+                return None;
+            }
+            dt.fraction = rest[1..1 + len].to_string();
+            rest = &rest[1 + len..];
+        }
+
+        dt.offset = match rest {
+            "" => None,
+            "Z" | "z" => Some(0),
+            _ => {
+                let sign = match rest.get(0..1) {
+                    Some("+") => 1,
+                    Some("-") => -1,
+                    _ => return None,
+                };
+                match (rest.len(), rest.get(3..4), digits(rest, 1, 3), digits(rest, 4, 6)) {
+                    (6, Some(":"), Some(hours), Some(minutes)) if hours < 24 && minutes < 60 => {
+                        Some(sign * (hours as i64 * 60 + minutes as i64))
+                    }
+                    _ => return None,
+                }
+            }
+        };
+        Some(dt)
+    }
+
+    /// Seconds since the Unix epoch, taking a missing offset to be UTC.
+    fn timestamp(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * 86400 + self.hour as i64 * 3600 +
+        self.minute as i64 * 60 + self.second as i64 - self.offset.unwrap_or(0) * 60
+    }
+
+    /// The offset as `+HH:MM`, or `Z` for UTC, with `sep` between hours and minutes.
+    fn offset_text(&self, sep: &str, utc: &str) -> String {
+        match self.offset {
+            None => String::new(),
+            Some(0) => utc.to_string(),
+            Some(offset) => {
+                format!("{}{:02}{}{:02}",
+                        if offset < 0 { '-' } else { '+' },
+                        offset.abs() / 60,
+                        sep,
+                        offset.abs() % 60)
+            }
+        }
+    }
+}
+
+/// Writes `dt` in RFC 3339 (the default), or with a strftime-style `render` format supporting
+/// `%Y %y %m %d %H %M %S %f %j %s %z %F %T` and `%%`. Returns `None` for any other directive.
+pub fn render_datetime(dt: &DateTime, render: Option<&str>) -> Option<String> {
+    let format = match render {
+        Some(format) => format,
+        None => {
+            let fraction = if dt.fraction.is_empty() {
+                String::new()
+            } else {
+                format!(".{}", dt.fraction)
+            };
+            return Some(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{}",
+                                dt.year,
+                                dt.month,
+                                dt.day,
+                                dt.hour,
+                                dt.minute,
+                                dt.second,
+                                fraction,
+                                dt.offset_text(":", "Z")));
+        }
+    };
+
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let part = match chars.next() {
+            Some('Y') => format!("{:04}", dt.year),
+            Some('y') => format!("{:02}", dt.year % 100),
+            Some('m') => format!("{:02}", dt.month),
+            Some('d') => format!("{:02}", dt.day),
+            Some('H') => format!("{:02}", dt.hour),
+            Some('M') => format!("{:02}", dt.minute),
+            Some('S') => format!("{:02}", dt.second),
+            Some('f') => dt.fraction.clone(),
+            Some('j') => {
+                format!("{:03}",
+                        days_from_civil(dt.year, dt.month, dt.day) -
+                        days_from_civil(dt.year, 1, 1) + 1)
+            }
+            Some('s') => dt.timestamp().to_string(),
+            Some('z') => dt.offset_text("", "+0000"),
+            Some('F') => format!("{:04}-{:02}-{:02}", dt.year, dt.month, dt.day),
+            Some('T') => format!("{:02}:{:02}:{:02}", dt.hour, dt.minute, dt.second),
+            Some('%') => "%".to_string(),
+            _ => return None,
+        };
+        out.push_str(&part);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert!(parse_duration("5m") == Some(300.0));
+        assert!(parse_duration("2h30m") == Some(9000.0));
+        assert!(parse_duration("1.5h") == Some(5400.0));
+        assert!(parse_duration("00:30:00") == Some(1800.0));
+        assert!(parse_duration("1:30") == Some(90.0));
+        assert!(parse_duration("5 minutes").is_none());
+        assert!(parse_duration("1:2:3:4").is_none());
+        assert!(parse_duration("").is_none());
+
+        assert!(render_duration(9000.0, None) == Some("9000".to_string()));
+        assert!(render_duration(9000.0, Some("hms")) == Some("02:30:00".to_string()));
+        assert!(render_duration(1.5, Some("fortnights")).is_none());
+    }
+
+    #[test]
+    fn sizes() {
+        assert!(parse_size("512M") == Some(512 << 20));
+        assert!(parse_size("2G") == Some(2 << 30));
+        assert!(parse_size("1.5KiB") == Some(1536));
+        assert!(parse_size("3MB") == Some(3000000));
+        assert!(parse_size("100") == Some(100));
+        assert!(parse_size("lots").is_none());
+
+        assert!(render_size(512 << 20, Some("mib")) == Some("512".to_string()));
+        assert!(render_size(1536, Some("kib")) == Some("1.5".to_string()));
+        assert!(render_size(1536, Some("original")).is_none());
+    }
+
+    #[test]
+    fn datetimes() {
+        let dt = DateTime::parse("2017-03-01T12:30:05.25+01:00").unwrap();
+        assert!(render_datetime(&dt, None) == Some("2017-03-01T12:30:05.25+01:00".to_string()));
+        assert!(render_datetime(&dt, Some("%Y%m%d %T %z")) ==
+                Some("20170301 12:30:05 +0100".to_string()));
+        assert!(render_datetime(&dt, Some("%s")) == Some("1488367805".to_string()));
+        assert!(render_datetime(&dt, Some("%j")) == Some("060".to_string()));
+        assert!(render_datetime(&dt, Some("%Q")).is_none());
+
+        let date = DateTime::parse("2016-02-29").unwrap();
+        assert!(render_datetime(&date, Some("%F")) == Some("2016-02-29".to_string()));
+        assert!(DateTime::parse("2017-02-29").is_none());
+        assert!(DateTime::parse("2017-03-01T25:00:00Z").is_none());
+        assert!(DateTime::parse("yesterday").is_none());
+    }
+}
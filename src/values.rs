@@ -0,0 +1,152 @@
+use errors::*;
+use structs::{FieldData, FieldSetting};
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use serde_yaml;
+
+/// Reads a value the way it would be read if written inline in a spec, so that `10` is a uint,
+/// `0.5` a float and `true` a bool. Anything else is a string.
+pub fn parse_value(text: &str) -> FieldData {
+    serde_yaml::from_str::<FieldData>(text)
+        .ok()
+        .and_then(|datum| if datum == FieldData::Future {
+            None
+        } else {
+            Some(datum)
+        })
+        .unwrap_or_else(|| FieldData::Str(text.to_string()))
+}
+
+/// Reads a parameter setting from the command line: a comma-separated list of values, or a
+/// single value or range written as it would be in a spec, e.g. `{from: 0, to: 10, step: 2}`.
+pub fn parse_setting(text: &str) -> FieldSetting {
+    let text = text.trim();
+    if text.contains(',') && !text.starts_with('{') && !text.starts_with('[') {
+        return FieldSetting::List(text.split(',').map(|v| parse_value(v.trim())).collect());
+    }
+    match serde_yaml::from_str::<FieldSetting>(text) {
+        Ok(FieldSetting::Value(_)) | Err(_) => FieldSetting::Value(parse_value(text)),
+        Ok(setting) => setting,
+    }
+}
+
+/// Splits CSV text (or TSV, with a tab `delimiter`) into records. Fields may be quoted, with `""`
+/// standing for a quote inside a quoted field.
+pub fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => record.push(field.split_off(0)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(field.split_off(0));
+                records.push(record.split_off(0));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+    records
+}
+
+/// The delimiter of a table file: tabs for `.tsv` files, commas otherwise.
+fn delimiter(path: &Path) -> char {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsv") => '\t',
+        _ => ',',
+    }
+}
+
+/// Reads a CSV or TSV file with a header row, returning the header and the remaining records.
+pub fn read_table(path: &Path) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut records = parse_csv(&read_to_string(path)?, delimiter(path)).into_iter();
+    let header = records.next()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .collect();
+    Ok((header, records.collect()))
+}
+
+fn read_to_string(path: &Path) -> Result<String> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Reads the values in a file: one per line, skipping blank lines and `#` comments, or the named
+/// column of a CSV file with a header row.
+pub fn read_values(path: &Path, column: Option<&str>) -> Result<Vec<FieldData>> {
+    match column {
+        None => {
+            Ok(read_to_string(path)?
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(parse_value)
+                .collect())
+        }
+        Some(column) => {
+            let (header, records) = read_table(path)?;
+            let index = header.iter()
+                .position(|name| name == column)
+                .ok_or_else(|| {
+                    Error::from(ErrorKind::UnknownColumn(path.display().to_string(),
+                                                         column.to_string()))
+                })?;
+            Ok(records.iter()
+                .filter_map(|record| record.get(index))
+                .map(|value| parse_value(value.trim()))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert!(parse_value("10") == FieldData::UInt(10));
+        assert!(parse_value("0.5") == FieldData::Float(0.5));
+        assert!(parse_value("data/a.bin") == FieldData::Str("data/a.bin".to_string()));
+        assert!(parse_value("") == FieldData::Str("".to_string()));
+        assert!(parse_value("[1, 2]") ==
+                FieldData::List(vec![FieldData::UInt(1), FieldData::UInt(2)]));
+    }
+
+    #[test]
+    fn parse_settings() {
+        assert!(parse_setting("1,2,3").vectorize("k").unwrap() ==
+                vec![FieldData::UInt(1), FieldData::UInt(2), FieldData::UInt(3)]);
+        assert!(parse_setting("{from: 0, to: 4, step: 2}").vectorize("k").unwrap() ==
+                vec![FieldData::UInt(0), FieldData::UInt(2), FieldData::UInt(4)]);
+        assert!(parse_setting("a.bin").vectorize("graph").unwrap() ==
+                vec![FieldData::Str("a.bin".to_string())]);
+    }
+
+    #[test]
+    fn parse_quoted_csv() {
+        let records = parse_csv("graph,k\n\"a,b.bin\",10\r\n\"say \"\"hi\"\"\",20\n\n", ',');
+        assert!(records ==
+                vec![vec!["graph".to_string(), "k".to_string()],
+                     vec!["a,b.bin".to_string(), "10".to_string()],
+                     vec!["say \"hi\"".to_string(), "20".to_string()]]);
+    }
+}
@@ -1,3 +1,6 @@
+// error_chain! recurses once per error kind, past the default limit
+#![recursion_limit = "256"]
+
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
@@ -13,24 +16,52 @@ extern crate itertools;
 extern crate maplit;
 extern crate rustc_serialize;
 extern crate glob;
+extern crate rmp;
+extern crate flate2;
+extern crate zstd;
+extern crate crypto;
 
 mod structs;
 mod errors;
+mod expr;
+mod template;
+mod planfile;
+mod cache;
+mod completions;
+mod init;
+mod migrate;
+mod values;
+mod sample;
+mod shell;
+mod units;
+mod which;
 
 use docopt::Docopt;
-use std::fs::File;
-use std::collections::HashMap;
+use std::cmp::max;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::collections::{BTreeMap, HashMap};
 use glob::glob;
 
 use structs::*;
 use errors::*;
+use planfile::*;
+use cache::PlanCache;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 const USAGE: &'static str = "
 Waluigi task builder
 
 Usage:
-  waluigi debug <experiment> [options]
+  waluigi debug <experiment> [--priority <job=n>]... [--set <param=value>]... [--var <name=value>]... [--output <path>] [--format <fmt>] [--shards <n> | --shard-size <k>] [options]
+  waluigi convert <input> <output> [--format <fmt>] [--json-errors]
+  waluigi completions <shell> [options]
+  waluigi init <name> [--bin <path>] [--dir <dir>] [--json-errors]
+  waluigi estimate <experiment> [--set <param=value>]... [--var <name=value>]... [--core-hour-price <p>] [--gb-hour-price <p>] [options]
+  waluigi migrate <spec>... [--json-errors]
   waluigi (-h | --help)
   waluigi --version
 
@@ -38,24 +69,110 @@ Options:
   -h --help             Show this screen.
   --version             Show version information.
   --program <path>      Add <path> to program specifications. By default, ./ and ./programs/ are searched for program specifications.
+  --prefer <path>       When several files define the same program, use the one in <path>.
+  --deny-warnings       Treat warnings about the specs or plan as errors.
+  --allow-extra-params  Only warn about parameters that the program of their job has no field for, instead of failing.
+  --tags <tags>         Only plan jobs with one of these comma-separated tags, along with the jobs they depend on.
+  --exclude-tags <tags>  Leave out jobs with any of these comma-separated tags, and the jobs that depend on them.
+  --stale-only          Only plan the jobs with a file output that is missing or older than a file among their path parameters, along with the jobs that depend on them, as make would.
+  --sample <n>          Only plan a pilot subset of <n> of the jobs without dependencies, chosen at random, along with the jobs that depend on them.
+  --stratify-by <param>  Split the --sample between the values of <param> in proportion to their jobs, so that each value is represented.
+  --sample-seed <s>     Seed of the --sample draw [default: 0].
+  --resolve-bins        Find the binary of every program at plan time, recording its absolute path in the plan, and fail early if one is missing.
+  --tool-dir <dir>      Search <dir> for binaries before the PATH with --resolve-bins.
+  --json-errors         On failure, print the error to stderr as a JSON object with a stable code field.
+  --cache <dir>         Reuse the planned jobs stored in <dir> for every job whose spec is unchanged, and store the rest.
+  --set <param=value>   Override a parameter of every job that sets it, or with <job>.<param>=<value>, of every job running <job>.
+                        Values may be comma-separated lists, or ranges such as {from: 0, to: 10, step: 2}.
+  --var <name=value>    Override a variable declared in the vars of the experiment.
+  --priority <job=n>    Override the priority of every job running program <job>. Higher priorities are dispatched first.
+  --core-hour-price <p>  Price of one core-hour, used to project the cost of the plan.
+  --gb-hour-price <p>   Price of one GB-hour of memory, used to project the cost of the plan.
+  --output <path>       Write the plan to <path> instead of stdout.
+  --store <dir>         Send the outputs of every job to <dir>/<job hash> in place of its output_dir, file outputs included, recording the program and parameters of each hash in <dir>/manifest.json.
+  --format <fmt>        Plan format: json or msgpack. By default, it is guessed from the output path (.msgpack or .mpk for msgpack).
+                        Output paths ending in .gz or .zst are compressed with gzip or zstd.
+  --shards <n>          Split the plan into <n> files next to --output, keeping dependent jobs together.
+  --shard-size <k>      Split the plan into files of roughly <k> jobs each, keeping dependent jobs together.
+  --bin <path>          Binary of the new program. Defaults to target/release/<name>.
+  --dir <dir>           Directory to write the new specs to [default: programs].
+
+Exit status:
+  0  success
+  1  internal error
+  2  invalid command line
+  3  a spec or plan could not be parsed
+  4  the specs are invalid, or do not fit the command line
+  5  the experiment could not be planned
+  6  some jobs failed (reserved for the tools that run plans)
 ";
 
 #[derive(Debug, RustcDecodable)]
 struct Args {
     cmd_debug: bool,
+    cmd_estimate: bool,
+    cmd_convert: bool,
+    cmd_completions: bool,
+    cmd_init: bool,
+    cmd_migrate: bool,
+    arg_shell: String,
+    arg_name: String,
     arg_experiment: String,
+    arg_input: String,
+    arg_output: String,
+    arg_spec: Vec<String>,
     flag_program: Vec<String>,
+    flag_cache: Option<String>,
+    flag_prefer: Vec<String>,
+    flag_deny_warnings: bool,
+    flag_allow_extra_params: bool,
+    flag_tags: Option<String>,
+    flag_exclude_tags: Option<String>,
+    flag_stale_only: bool,
+    flag_sample: Option<usize>,
+    flag_stratify_by: Option<String>,
+    flag_sample_seed: u64,
+    flag_resolve_bins: bool,
+    flag_tool_dir: Vec<String>,
+    flag_json_errors: bool,
+    flag_priority: Vec<String>,
+    flag_set: Vec<String>,
+    flag_var: Vec<String>,
+    flag_core_hour_price: Option<f64>,
+    flag_gb_hour_price: Option<f64>,
+    flag_output: Option<String>,
+    flag_store: Option<String>,
+    flag_format: Option<String>,
+    flag_shards: Option<usize>,
+    flag_shard_size: Option<usize>,
+    flag_bin: Option<String>,
+    flag_dir: String,
 }
 
-fn load_program_specs(given: Vec<String>) -> Result<HashMap<String, Program>> {
+/// Whether two paths name the same file.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn load_program_specs(given: Vec<String>, prefer: &[String]) -> Result<HashMap<String, Program>> {
     let mut progs = vec![];
     for entry in glob("./*.yaml")
         .expect("failed to parse glob pattern")
         .chain(glob("./programs/*.yaml").expect("failed to parse glob pattern")) {
-        let prog: Option<Program> = match entry {
+        let prog: Option<(PathBuf, Program)> = match entry {
             Ok(path) => {
-                serde_yaml::from_reader(File::open(path.clone()).unwrap())
-                    .map(|x| Some(x))
+                let mut source = String::new();
+                File::open(path.clone()).unwrap().read_to_string(&mut source)?;
+                // checked before parsing, so that a program of a newer layout is reported
+                // instead of being skipped like any other YAML file that is not a program
+                if let Ok(version) = migrate::version(&source) {
+                    migrate::check_version(&path.display().to_string(), version)?;
+                }
+                serde_yaml::from_str(&source)
+                    .map(|x| Some((path, x)))
                     .unwrap_or_else(|e| {
                         // println!("failed to read program from {:?}: {:?}", path, e);
                         None
@@ -72,25 +189,496 @@ fn load_program_specs(given: Vec<String>) -> Result<HashMap<String, Program>> {
     }
 
     for path in given {
-        progs.push(serde_yaml::from_reader(File::open(path)?)?);
+        let source = read_spec(&path)?;
+        let prog = serde_yaml::from_str(&source)?;
+        progs.push((PathBuf::from(path), prog));
+    }
+
+    pick_programs(progs, prefer)
+}
+
+/// Keeps one definition of each program found. A program defined in two files is an error,
+/// unless exactly one of them is preferred.
+fn pick_programs(progs: Vec<(PathBuf, Program)>,
+                 prefer: &[String])
+                 -> Result<HashMap<String, Program>> {
+    let preferred = |path: &Path| prefer.iter().any(|p| same_file(Path::new(p), path));
+    let mut found: HashMap<String, (PathBuf, Program)> = HashMap::new();
+    for (path, prog) in progs {
+        let replace = match found.get(&prog.name) {
+            None => true,
+            Some(&(ref existing, _)) if same_file(existing, &path) => false,
+            Some(&(ref existing, _)) => {
+                match (preferred(existing.as_path()), preferred(path.as_path())) {
+                    (true, false) => false,
+                    (false, true) => true,
+                    _ => {
+                        return Err(ErrorKind::DuplicateProgram(prog.name.clone(),
+                                                               existing.display().to_string(),
+                                                               path.display().to_string())
+                            .into())
+                    }
+                }
+            }
+        };
+
+        if replace {
+            found.insert(prog.name.clone(), (path, prog));
+        }
+    }
+
+    Ok(found.into_iter().map(|(name, (_, prog))| (name, prog)).collect())
+}
+
+/// Reads a spec, checking that this build understands its version.
+fn read_spec(path: &str) -> Result<String> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    migrate::check_source(path, &source)?;
+    Ok(source)
+}
+
+/// Loads an experiment, applying `--set` and `--var` overrides before any value files are read.
+fn load_experiment(experiment: &str, sets: &[String], vars: &[String]) -> Result<Experiment> {
+    let mut exp: Experiment = serde_yaml::from_str(&read_spec(experiment)?)?;
+    exp.apply_shared_parameters()?;
+    for spec in sets {
+        let (job, param, setting) = parse_override(spec)?;
+        exp.set_parameter(job.as_ref().map(|j| j.as_str()), &param, setting)?;
+    }
+    for spec in vars {
+        let mut parts = spec.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => exp.set_var(name, values::parse_value(value))?,
+            _ => return Err(ErrorKind::InvalidOverride(spec.to_string()).into()),
+        }
+    }
+    exp.apply_vars();
+    exp.read_value_files(Path::new(experiment).parent().unwrap_or(Path::new(".")))?;
+    Ok(exp)
+}
+
+/// Rewrites each spec in place to the current layout.
+fn migrate_specs(paths: &[String]) -> Result<()> {
+    for path in paths {
+        let mut source = String::new();
+        File::open(path)?.read_to_string(&mut source)?;
+        match migrate::migrate(path, &source)? {
+            Some(migrated) => {
+                File::create(path)?.write_all(migrated.as_bytes())?;
+                println!("migrated {}", path);
+            }
+            None => println!("{} is up to date", path),
+        }
+    }
+    Ok(())
+}
+
+/// Splits a comma-separated list of tags.
+fn parse_tags(tags: &Option<String>) -> Vec<String> {
+    tags.as_ref()
+        .map(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Splits `[<job>.]<param>=<value>` into its parts.
+fn parse_override(spec: &str) -> Result<(Option<String>, String, FieldSetting)> {
+    let mut parts = spec.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => {
+            let (job, param) = match name.rfind('.') {
+                Some(dot) => (Some(name[..dot].to_string()), &name[dot + 1..]),
+                None => (None, name),
+            };
+            Ok((job, param.to_string(), values::parse_setting(value)))
+        }
+        _ => Err(ErrorKind::InvalidOverride(spec.to_string()).into()),
+    }
+}
+
+fn parse_priority(spec: &str) -> Result<(String, i64)> {
+    let mut parts = spec.splitn(2, '=');
+    match (parts.next(), parts.next().map(|p| p.parse::<i64>())) {
+        (Some(job), Some(Ok(priority))) => Ok((job.to_string(), priority)),
+        _ => Err(ErrorKind::InvalidPriority(spec.to_string()).into()),
+    }
+}
+
+/// Hourly prices used to turn an estimate into a projected cost.
+struct Prices {
+    core_hour: f64,
+    gb_hour: f64,
+}
+
+/// Writes the total estimated core-hours of the plan to `out`, per program, along with the
+/// projected cost if prices were given.
+fn write_estimate<W: Write>(out: &mut W,
+                            plan: &[JobInstance],
+                            prices: Option<Prices>)
+                            -> Result<()> {
+    // (jobs, jobs without an estimate, core-seconds, GB-seconds)
+    let mut rows: BTreeMap<&str, (usize, usize, f64, f64)> = BTreeMap::new();
+    for inst in plan {
+        let row = rows.entry(&inst.program).or_insert((0, 0, 0.0, 0.0));
+        row.0 += 1;
+        match inst.runtime {
+            Some(secs) => {
+                row.2 += secs * inst.threads as f64;
+                row.3 += secs * inst.memory.unwrap_or(0.0);
+            }
+            None => row.1 += 1,
+        }
+    }
+
+    let cost = |core_secs: f64, gb_secs: f64| {
+        prices.as_ref()
+            .map(|p| format!("{:>12.2}", (core_secs * p.core_hour + gb_secs * p.gb_hour) / 3600.0))
+            .unwrap_or_default()
+    };
+
+    writeln!(out,
+             "{:<24} {:>10} {:>12} {:>12} {:>12}{}",
+             "program",
+             "jobs",
+             "unestimated",
+             "core-hours",
+             "GB-hours",
+             prices.as_ref().map(|_| format!(" {:>12}", "cost")).unwrap_or_default())?;
+    let mut total = (0, 0, 0.0, 0.0);
+    for (program, &(jobs, unestimated, core_secs, gb_secs)) in &rows {
+        writeln!(out,
+                 "{:<24} {:>10} {:>12} {:>12.2} {:>12.2} {}",
+                 program,
+                 jobs,
+                 unestimated,
+                 core_secs / 3600.0,
+                 gb_secs / 3600.0,
+                 cost(core_secs, gb_secs))?;
+        total = (total.0 + jobs,
+                 total.1 + unestimated,
+                 total.2 + core_secs,
+                 total.3 + gb_secs);
+    }
+    writeln!(out,
+             "{:<24} {:>10} {:>12} {:>12.2} {:>12.2} {}",
+             "total",
+             total.0,
+             total.1,
+             total.2 / 3600.0,
+             total.3 / 3600.0,
+             cost(total.2, total.3))?;
+    Ok(())
+}
+
+/// Picks the plan format from `--format`, falling back to the extension of `path`.
+fn plan_format(format: &Option<String>, path: Option<&str>) -> Result<PlanFormat> {
+    match (format, path) {
+        (&Some(ref fmt), _) => fmt.parse(),
+        (&None, Some(path)) => Ok(PlanFormat::from_path(path)),
+        (&None, None) => Ok(PlanFormat::Json),
+    }
+}
+
+/// Writes the plan to `path`, or stdout if no path is given.
+fn write_plan<I>(plan: I, path: Option<&str>, format: PlanFormat) -> Result<()>
+    where I: IntoIterator<Item = Result<PlanEntry>>
+{
+    let mut writer = PlanWriter::new(Sink::create(path)?, format);
+    for entry in plan {
+        writer.write_entry(&entry?)?;
+    }
+    writer.into_inner().finish()
+}
+
+/// The entries of a plan file for `plan`: the headers of its programs, then its jobs.
+fn plan_entries(plan: Vec<JobInstance>, programs: &HashMap<String, Program>) -> Vec<PlanEntry> {
+    program_headers(&plan, programs)
+        .into_iter()
+        .map(PlanEntry::Program)
+        .chain(plan.into_iter().map(PlanEntry::Job))
+        .collect()
+}
+
+/// Prints warnings about the specs or plan to stderr, failing if `deny` is set and there were any.
+fn check_warnings(warnings: &[Warning], deny: bool) -> Result<()> {
+    for warning in warnings {
+        writeln!(io::stderr(), "warning: {}", warning)?;
     }
 
-    Ok(progs.into_iter().map(|prog| (prog.name.clone(), prog)).collect())
+    if deny && !warnings.is_empty() {
+        Err(ErrorKind::DeniedWarnings(warnings.len()).into())
+    } else {
+        Ok(())
+    }
 }
 
-fn load_experiment(experiment: String) -> Result<Experiment> {
-    Ok(serde_yaml::from_reader(File::open(experiment)?)?)
+/// An error as printed by `--json-errors`.
+#[derive(Serialize)]
+struct ErrorReport {
+    code: &'static str,
+    class: &'static str,
+    message: String,
+    causes: Vec<String>,
+}
+
+/// Prints an error and its causes to stderr.
+fn report(err: &Error, json: bool) -> Result<()> {
+    let mut stderr = io::stderr();
+    if json {
+        let report = ErrorReport {
+            code: err.kind().code(),
+            class: err.kind().class().name(),
+            message: err.to_string(),
+            causes: err.iter().skip(1).map(|e| e.to_string()).collect(),
+        };
+        serde_json::to_writer(&mut stderr, &report)?;
+        stderr.write_all(b"\n")?;
+    } else {
+        writeln!(stderr, "error[{}]: {}", err.kind().code(), err)?;
+        for cause in err.iter().skip(1) {
+            writeln!(stderr, "caused by: {}", cause)?;
+        }
+    }
+    Ok(())
 }
 
 fn main() {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.version(Some(env!("CARGO_PKG_VERSION").to_string())).decode())
-        .unwrap_or_else(|e| e.exit());
+        .unwrap_or_else(|e| if e.fatal() {
+            let _ = writeln!(io::stderr(), "{}", e);
+            process::exit(2)
+        } else {
+            e.exit()
+        });
+
+    let json = args.flag_json_errors;
+    if let Err(err) = run(args) {
+        let _ = report(&err, json);
+        process::exit(err.kind().class().exit_code());
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    if args.cmd_init {
+        let (program, experiment) = init::init(&args.arg_name,
+                                               args.flag_bin.as_ref().map(|b| b.as_str()),
+                                               Path::new(&args.flag_dir))?;
+        println!("wrote {} and {}", program.display(), experiment.display());
+        return Ok(());
+    }
+
+    if args.cmd_migrate {
+        return migrate_specs(&args.arg_spec);
+    }
+
+    if args.cmd_convert {
+        let input = open_plan(&args.arg_input)?;
+        let format = plan_format(&args.flag_format, Some(&args.arg_output))?;
+        return write_plan(input, Some(&args.arg_output), format);
+    }
+
+    let mut progs = load_program_specs(args.flag_program, &args.flag_prefer)?;
+    if args.cmd_completions {
+        let mut names = progs.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        print!("{}", completions::completions(&args.arg_shell, USAGE, &names)?);
+        return Ok(());
+    }
+
+    let mut exp = load_experiment(&args.arg_experiment, &args.flag_set, &args.flag_var)?;
+    for spec in &args.flag_priority {
+        let (job, priority) = parse_priority(spec)?;
+        exp.set_priority(&job, priority)?;
+    }
+    if let Some(ref output) = args.flag_output {
+        // response files are kept with the plan that refers to them. the path is absolute, since
+        // the plan may be run from anywhere
+        let plan = env::current_dir()?.join(output);
+        let dir = plan.parent().unwrap_or(&plan).join("args");
+        exp.default_response_dir(&dir.to_string_lossy());
+    }
+    if let Some(ref dir) = args.flag_store {
+        exp.set_store(dir);
+    }
+    if args.flag_resolve_bins {
+        for name in exp.programs() {
+            if let Some(prog) = progs.get_mut(name) {
+                prog.resolve_bin(&args.flag_tool_dir)?;
+            }
+        }
+    }
+
+    if !args.flag_allow_extra_params {
+        exp.check_parameters(&progs)?;
+    }
+    check_warnings(&exp.warnings(&progs), args.flag_deny_warnings)?;
+
+    let cache = args.flag_cache.as_ref().map(PlanCache::new);
+    let plan = exp.plan_cached(&progs, cache.as_ref())?;
+    let plan = select_tags(plan,
+                           &parse_tags(&args.flag_tags),
+                           &parse_tags(&args.flag_exclude_tags));
+    let plan = if args.flag_stale_only {
+        select_stale(plan,
+                     &progs,
+                     |path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+    } else {
+        plan
+    };
+    let plan = match args.flag_sample {
+        Some(n) => {
+            sample_plan(plan,
+                        n,
+                        args.flag_stratify_by.as_ref().map(|p| p.as_str()),
+                        args.flag_sample_seed)
+        }
+        None => plan,
+    };
+    check_warnings(&missing_required_files(&plan, |path| Path::new(path).exists()),
+                   args.flag_deny_warnings)?;
+    if args.cmd_estimate {
+        let prices = if args.flag_core_hour_price.is_some() || args.flag_gb_hour_price.is_some() {
+            Some(Prices {
+                core_hour: args.flag_core_hour_price.unwrap_or(0.0),
+                gb_hour: args.flag_gb_hour_price.unwrap_or(0.0),
+            })
+        } else {
+            None
+        };
+        write_estimate(&mut io::stdout(), &plan, prices)?;
+    } else {
+        if let Some(ref dir) = args.flag_store {
+            write_store_manifest(&plan, Path::new(dir))?;
+        }
+        for inst in &plan {
+            inst.response_files.write()?;
+        }
+        let path = args.flag_output.as_ref().map(|p| p.as_str());
+        let format = plan_format(&args.flag_format, path)?;
+        let shards = match (args.flag_shards, args.flag_shard_size) {
+            (Some(n), _) => Some(max(n, 1)),
+            (None, Some(k)) => {
+                let k = max(k, 1);
+                Some(max((plan.len() + k - 1) / k, 1))
+            }
+            (None, None) => None,
+        };
+
+        if let Some(shards) = shards {
+            let path = path.ok_or(ErrorKind::ShardsWithoutOutput)?;
+            for (i, part) in shard(plan, shards).into_iter().enumerate() {
+                write_plan(plan_entries(part, &progs).into_iter().map(Ok),
+                           Some(&shard_path(path, i)),
+                           format)?;
+            }
+        } else {
+            write_plan(plan_entries(plan, &progs).into_iter().map(Ok), path, format)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    let progs = load_program_specs(args.flag_program).unwrap();
-    let exp = load_experiment(args.arg_experiment).unwrap();
+    fn prog(max_concurrent: usize) -> Program {
+        serde_yaml::from_str(&format!("
+            name: est
+            bin: est
+            format: ''
+            outputs: {{}}
+            fields: {{}}
+            max_concurrent: {}
+            ",
+                                      max_concurrent))
+            .unwrap()
+    }
+
+    #[test]
+    fn duplicate_programs() {
+        let progs = || {
+            vec![(PathBuf::from("a/est.yaml"), prog(1)), (PathBuf::from("b/est.yaml"), prog(2))]
+        };
+        let picked = |prefer: &[&str]| {
+            let prefer = prefer.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            pick_programs(progs(), &prefer).map(|progs| progs["est"].header().max_concurrent)
+        };
+
+        let err = picked(&[]).unwrap_err();
+        assert!(err.kind().code() == "duplicate_program");
+        assert!(err.to_string().contains("a/est.yaml") && err.to_string().contains("b/est.yaml"));
+        assert!(picked(&["a/est.yaml"]).unwrap() == Some(1));
+        assert!(picked(&["b/est.yaml"]).unwrap() == Some(2));
+        assert!(picked(&["a/est.yaml", "b/est.yaml"]).is_err());
+
+        // the same file found twice is one definition
+        let same = vec![(PathBuf::from("a/est.yaml"), prog(1)),
+                        (PathBuf::from("a/est.yaml"), prog(1))];
+        assert!(pick_programs(same, &[]).unwrap().len() == 1);
+    }
 
-    for job in exp.plan(1, &progs).unwrap() {
-        println!("{}", serde_json::to_string(&job).unwrap());
+    #[test]
+    fn plan_carries_max_concurrent() {
+        let exp: Experiment = serde_yaml::from_str("jobs: [{run: est, repetitions: 2}]").unwrap();
+        let progs = hashmap!{ "est".to_string() => prog(2) };
+        let plan = exp.plan(&progs).unwrap();
+        assert!(plan.len() == 2);
+
+        let mut writer = PlanWriter::new(vec![], PlanFormat::Json);
+        for entry in &plan_entries(plan, &progs) {
+            writer.write_entry(entry).unwrap();
+        }
+        let written = String::from_utf8(writer.into_inner()).unwrap();
+        // once, in the header of the program, which its jobs refer to by name
+        let lines = written.lines().collect::<Vec<_>>();
+        assert!(lines.len() == 3);
+        assert!(lines[0].contains(r#""max_concurrent":2"#));
+        assert!(lines[1..].iter().all(|line| !line.contains("max_concurrent")));
+        assert!(lines[1..].iter().all(|line| line.contains(r#""program":"est""#)));
+    }
+
+    #[test]
+    fn estimate_projects_cost() {
+        let prog: Program = serde_yaml::from_str("
+            name: est
+            bin: est
+            format: '<k>'
+            outputs: {}
+            fields:
+              k:
+                type: uint
+            runtime: 'k * 3600'
+            memory: 2
+            ")
+            .unwrap();
+        let exp: Experiment = serde_yaml::from_str("jobs: [{run: est, parameters: {k: [1, 2]}}]")
+            .unwrap();
+        let plan = exp.plan(&hashmap!{ "est".to_string() => prog }).unwrap();
+        let table = |prices: Option<Prices>| {
+            let mut out = vec![];
+            write_estimate(&mut out, &plan, prices).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let prices = Prices {
+            core_hour: 0.5,
+            gb_hour: 0.25,
+        };
+        let priced = table(Some(prices));
+        let lines = priced.lines().collect::<Vec<_>>();
+        assert!(lines[0].trim_right().ends_with("cost"));
+        // 3 core-hours at 0.5 and 6 GB-hours at 0.25
+        let est = lines[1].split_whitespace().collect::<Vec<_>>();
+        assert!(est == ["est", "2", "0", "3.00", "6.00", "3.00"]);
+        assert!(lines[2].starts_with("total"));
+        assert!(lines[2].trim_right().ends_with("3.00"));
+        assert!(!table(None).contains("cost"));
     }
 }
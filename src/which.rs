@@ -0,0 +1,53 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether `path` is a file that can be run.
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Finds the executable a command would run, the way a shell does: binaries given as a path are
+/// taken relative to the working directory, and bare names are looked up in `dirs` and then in
+/// `PATH`. On failure, returns the locations searched.
+pub fn which(bin: &str, dirs: &[String]) -> Result<PathBuf, Vec<PathBuf>> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let candidates = if Path::new(bin).components().count() > 1 {
+        vec![cwd.join(bin)]
+    } else {
+        let path = env::var_os("PATH").unwrap_or_default();
+        dirs.iter()
+            .map(PathBuf::from)
+            .chain(env::split_paths(&path))
+            .map(|dir| cwd.join(dir).join(bin))
+            .collect()
+    };
+
+    match candidates.iter().find(|path| is_executable(path)) {
+        Some(path) => Ok(path.clone()),
+        None => Err(candidates),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn which_searches_path() {
+        assert!(which("sh", &[]).unwrap().is_absolute());
+        let searched = which("no-such-binary", &["tools".to_string()]).unwrap_err();
+        assert!(searched[0].ends_with("tools/no-such-binary"));
+        assert!(which("programs/curv.yaml", &[]).is_err());
+    }
+}